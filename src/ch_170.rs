@@ -1,56 +1,77 @@
+use crate::config::HidConfig;
 use crate::helpers::retry_with_backoff;
-use crate::sensor_readings::SensorReadings;
-use anyhow::{Context, Result};
-use hidapi::{HidApi, HidDevice};
+use crate::sensor_readings::{Metric, SensorReadings};
+use crate::shutdown::Shutdown;
+use anyhow::{Context, Result, bail};
+use hidapi::{DeviceInfo, HidApi, HidDevice};
+use serde::Deserialize;
 use tracing::{debug, info, warn};
 use zerocopy::{BE, Immutable, IntoBytes, byteorder};
 
 // Constants
-const DEEPCOOL_VENDOR_ID: u16 = 13875;
-const CH170_PRODUCT_ID: u16 = 19;
-
 const DISPLAY_REPORT_ID: u8 = 16;
 const DISPLAY_TERMINATOR: u8 = 22;
 const DISPLAY_HEADER: [u8; 5] = [104, 1, 6, 35, 1];
 const DISPLAY_PAYLOAD_SIZE: usize = 64;
 const DISPLAY_PADDING_SIZE: usize = 22;
-
-const MAX_CONNECTION_RETRIES: u32 = 3;
-const RETRY_DELAY_SECS: u64 = 5;
+/// Shown for unavailable (NaN) readings, since the protocol has no blank marker
+const UNAVAILABLE_VALUE: f64 = 0.0;
 
 // Display Device
 pub struct CH170Display {
-    device: HidDevice,
+    output: Output,
     payload: DisplayPayload,
     mode: DisplayMode,
+    hid: HidConfig,
+    shutdown: Shutdown,
+}
+
+/// Where frames go: the HID device, or stdout for a dry run
+enum Output {
+    Device(HidDevice),
+    DryRun,
 }
 
 impl CH170Display {
-    pub fn new() -> Result<Self> {
-        let device = connect_to_display()?;
-        let payload = DisplayPayload::new();
-        let mode = DisplayMode::default();
-
-        Ok(Self {
-            device,
-            payload,
-            mode,
-        })
+    pub fn new(hid: &HidConfig, shutdown: &Shutdown) -> Result<Self> {
+        let device = connect_to_display(hid, shutdown)?;
+        Ok(Self::with_output(Output::Device(device), hid, shutdown))
+    }
+
+    /// A display that prints each frame as hex instead of writing it
+    pub fn dry_run(hid: &HidConfig, shutdown: &Shutdown) -> Self {
+        info!("Dry run, frames are printed instead of written to the display");
+        Self::with_output(Output::DryRun, hid, shutdown)
+    }
+
+    fn with_output(output: Output, hid: &HidConfig, shutdown: &Shutdown) -> Self {
+        Self {
+            output,
+            payload: DisplayPayload::new(),
+            mode: DisplayMode::default(),
+            hid: hid.clone(),
+            shutdown: shutdown.clone(),
+        }
     }
 
-    pub fn switch_mode(&mut self) {
-        self.mode.next();
-        debug!("Switched display mode to {:?}", self.mode);
+    pub fn set_mode(&mut self, mode: DisplayMode) {
+        self.mode = mode;
+        debug!("Set display mode to {:?}", self.mode);
     }
 
     pub fn update(&mut self, readings: &SensorReadings) -> Result<()> {
-        self.payload.update(self.mode, readings);
+        let mut shown = *readings;
+        for metric in Metric::ALL {
+            if shown.get(metric).is_nan() {
+                shown.set(metric, UNAVAILABLE_VALUE);
+            }
+        }
+        self.payload.update(self.mode, &shown);
 
         if let Err(err) = self.write_to_device() {
             warn!(?err, "HID write failed, reconnecting to display");
-            *self = Self::new()?;
+            self.output = Output::Device(connect_to_display(&self.hid, &self.shutdown)?);
             // Retry write after reconnection
-            self.payload.update(self.mode, readings);
             self.write_to_device()?;
         }
 
@@ -63,17 +84,32 @@ impl CH170Display {
 
     fn write_to_device(&mut self) -> Result<()> {
         let bytes = self.payload.as_bytes();
-        self.device
-            .write(bytes)
-            .context("Failed to write to HID device")?;
+        match &self.output {
+            Output::Device(device) => {
+                device
+                    .write(bytes)
+                    .context("Failed to write to HID device")?;
+            }
+            Output::DryRun => println!("{} {}", self.mode.name(), format_frame(bytes)),
+        }
         Ok(())
     }
 }
 
+fn format_frame(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 // Display Modes
-#[derive(Debug, Clone, Copy, IntoBytes, Immutable, PartialEq, Eq)]
+/// Spelled as [`DisplayMode::name`] in the config and on the command line
+#[derive(Debug, Clone, Copy, IntoBytes, Immutable, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
 #[repr(u8)]
-enum DisplayMode {
+pub enum DisplayMode {
     CpuFrequency = 2,
     CpuFan = 3,
     Gpu = 4,
@@ -86,14 +122,45 @@ impl Default for DisplayMode {
 }
 
 impl DisplayMode {
-    fn next(&mut self) {
-        *self = match self {
-            DisplayMode::CpuFrequency => DisplayMode::Gpu,
-            DisplayMode::Gpu => DisplayMode::CpuFan,
-            DisplayMode::CpuFan => DisplayMode::CpuFrequency,
+    pub const ALL: [DisplayMode; 3] = [
+        DisplayMode::CpuFrequency,
+        DisplayMode::Gpu,
+        DisplayMode::CpuFan,
+    ];
+
+    pub fn from_name(name: &str) -> Option<DisplayMode> {
+        DisplayMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisplayMode::CpuFrequency => "cpu-frequency",
+            DisplayMode::CpuFan => "cpu-fan",
+            DisplayMode::Gpu => "gpu",
         }
     }
 
+    /// Metrics only this mode shows, so it's worth showing while any has data
+    pub fn metrics(&self) -> &'static [Metric] {
+        match self {
+            DisplayMode::CpuFrequency => &[Metric::CpuFreq],
+            DisplayMode::CpuFan => &[Metric::CpuCoolerRpm],
+            DisplayMode::Gpu => &[
+                Metric::GpuTemp,
+                Metric::GpuPower,
+                Metric::GpuUsage,
+                Metric::GpuFreq,
+            ],
+        }
+    }
+
+    fn expected_names() -> String {
+        let names: Vec<_> = DisplayMode::ALL.iter().map(DisplayMode::name).collect();
+        names.join(", ")
+    }
+
     fn includes_cpu(&self) -> bool {
         matches!(self, DisplayMode::CpuFrequency | DisplayMode::CpuFan)
     }
@@ -103,6 +170,29 @@ impl DisplayMode {
     }
 }
 
+impl TryFrom<String> for DisplayMode {
+    type Error = String;
+
+    fn try_from(name: String) -> std::result::Result<Self, String> {
+        DisplayMode::from_name(&name).ok_or_else(|| {
+            format!(
+                "unknown display mode `{name}`, expected one of {}",
+                DisplayMode::expected_names()
+            )
+        })
+    }
+}
+
+impl clap::ValueEnum for DisplayMode {
+    fn value_variants<'a>() -> &'a [Self] {
+        &DisplayMode::ALL
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.name()))
+    }
+}
+
 // Display Data Structures
 #[derive(Default, IntoBytes, Immutable)]
 #[repr(C)]
@@ -201,20 +291,80 @@ const _: () = {
 };
 
 // HID Connection Functions
-fn connect_to_display() -> Result<HidDevice> {
-    retry_with_backoff(MAX_CONNECTION_RETRIES, RETRY_DELAY_SECS, open_hid_device)
+fn connect_to_display(hid: &HidConfig, shutdown: &Shutdown) -> Result<HidDevice> {
+    retry_with_backoff(
+        hid.max_connection_retries.get(),
+        hid.retry_delay_secs,
+        shutdown,
+        || open_hid_device(hid),
+    )
+}
+
+fn matching_devices<'a>(api: &'a HidApi, hid: &HidConfig) -> impl Iterator<Item = &'a DeviceInfo> {
+    let (vendor_id, product_id) = (hid.vendor_id, hid.product_id);
+    api.device_list()
+        .filter(move |info| info.vendor_id() == vendor_id && info.product_id() == product_id)
 }
 
-fn open_hid_device() -> Result<HidDevice> {
+/// A connected display, as shown by `list-devices`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayInfo {
+    pub path: String,
+    pub serial_number: Option<String>,
+    pub product: Option<String>,
+}
+
+impl DisplayInfo {
+    /// Whether `selector` names this display by serial number or HID path
+    pub fn matches(&self, selector: &str) -> bool {
+        self.serial_number.as_deref() == Some(selector) || self.path == selector
+    }
+}
+
+impl From<&DeviceInfo> for DisplayInfo {
+    fn from(info: &DeviceInfo) -> Self {
+        Self {
+            path: info.path().to_string_lossy().into_owned(),
+            serial_number: info
+                .serial_number()
+                .filter(|serial| !serial.is_empty())
+                .map(str::to_string),
+            product: info.product_string().map(str::to_string),
+        }
+    }
+}
+
+/// Every connected display with the configured vendor and product ID
+pub fn list_displays(hid: &HidConfig) -> Result<Vec<DisplayInfo>> {
     let api = HidApi::new().context("Failed to initialize HID API")?;
+    Ok(matching_devices(&api, hid).map(DisplayInfo::from).collect())
+}
 
-    let device = api
-        .open(DEEPCOOL_VENDOR_ID, CH170_PRODUCT_ID)
-        .context(format!(
-            "Failed to open HID device (VID: 0x{:04X}, PID: 0x{:04X}). \
+fn open_hid_device(hid: &HidConfig) -> Result<HidDevice> {
+    let api = HidApi::new().context("Failed to initialize HID API")?;
+    let (vendor_id, product_id) = (hid.vendor_id, hid.product_id);
+
+    let device = match &hid.device {
+        None => api.open(vendor_id, product_id),
+        Some(selector) => {
+            let Some(info) =
+                matching_devices(&api, hid).find(|info| DisplayInfo::from(*info).matches(selector))
+            else {
+                bail!(
+                    "No CH170 display (VID: 0x{:04X}, PID: 0x{:04X}) with serial number \
+                    or path {selector}. Run `list-devices` to see connected displays.",
+                    vendor_id,
+                    product_id
+                );
+            };
+            info.open_device(&api)
+        }
+    }
+    .context(format!(
+        "Failed to open HID device (VID: 0x{:04X}, PID: 0x{:04X}). \
             Is the CH170 display connected?",
-            DEEPCOOL_VENDOR_ID, CH170_PRODUCT_ID
-        ))?;
+        vendor_id, product_id
+    ))?;
 
     let device_info = device
         .get_device_info()
@@ -224,8 +374,8 @@ fn open_hid_device() -> Result<HidDevice> {
         .unwrap_or("CH170 Digital Display");
 
     info!(
-        vendor_id = DEEPCOOL_VENDOR_ID,
-        product_id = CH170_PRODUCT_ID,
+        vendor_id,
+        product_id,
         product = product_name,
         "HID connection established"
     );
@@ -240,13 +390,85 @@ mod tests {
     use super::*;
     use crate::sensor_readings::TemperatureUnit;
 
+    #[test]
+    fn test_display_info_matches_serial_or_path() {
+        let info = DisplayInfo {
+            path: "/dev/hidraw3".to_string(),
+            serial_number: Some("A1B2C3".to_string()),
+            product: Some("CH170 Digital Display".to_string()),
+        };
+        assert!(info.matches("A1B2C3"));
+        assert!(info.matches("/dev/hidraw3"));
+        assert!(!info.matches("/dev/hidraw4"));
+    }
+
+    #[test]
+    fn test_dry_run_frame() {
+        let mut display = CH170Display::dry_run(&HidConfig::default(), &Shutdown::new());
+        display.set_mode(DisplayMode::Gpu);
+        let readings = SensorReadings {
+            gpu_temp: 70.0,
+            gpu_usage: 80.0,
+            ..SensorReadings::default()
+        };
+        display.update(&readings).unwrap();
+
+        let frame = format_frame(display.payload.as_bytes());
+        assert_eq!(frame.len(), DISPLAY_PAYLOAD_SIZE * 3 - 1);
+        // Report ID, header, then the GPU mode byte
+        assert!(frame.starts_with("10 68 01 06 23 01 04"), "{frame}");
+    }
+
+    #[test]
+    fn test_unavailable_readings_sent_as_zero() {
+        let frame = |mode, readings: &SensorReadings| {
+            let mut display = CH170Display::dry_run(&HidConfig::default(), &Shutdown::new());
+            display.set_mode(mode);
+            display.update(readings).unwrap();
+            display.payload.as_bytes().to_vec()
+        };
+        let unavailable = SensorReadings {
+            cpu_temp: f64::NAN,
+            cpu_power: f64::NAN,
+            cpu_usage: f64::NAN,
+            cpu_freq: f64::NAN,
+            cpu_cooler_rpm: f64::NAN,
+            gpu_temp: f64::NAN,
+            gpu_power: f64::NAN,
+            gpu_usage: f64::NAN,
+            gpu_freq: f64::NAN,
+            ..SensorReadings::default()
+        };
+
+        for mode in [DisplayMode::CpuFrequency, DisplayMode::Gpu] {
+            let bytes = frame(mode, &unavailable);
+            assert_eq!(bytes, frame(mode, &SensorReadings::default()));
+            // No NaN bit pattern in the CPU or GPU temperature
+            assert_eq!(bytes[10..14], [0, 0, 0, 0]);
+            assert_eq!(bytes[21..25], [0, 0, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn test_display_mode_names() {
+        for mode in DisplayMode::ALL {
+            assert_eq!(DisplayMode::from_name(mode.name()), Some(mode));
+            // The config and the command line use the same spelling
+            let config: DisplayMode = serde_json::from_value(mode.name().into()).unwrap();
+            assert_eq!(config, mode);
+            let cli = <DisplayMode as clap::ValueEnum>::from_str(mode.name(), false);
+            assert_eq!(cli, Ok(mode));
+        }
+        assert_eq!(DisplayMode::from_name("psu"), None);
+    }
+
     #[test]
     fn test_display_with_dummy_values() {
         // This test connects to the actual CH170 display
         println!("\n=== Testing CH170 Display with Dummy Sensor Values ===\n");
 
         // Try to connect to the display
-        let mut display = match CH170Display::new() {
+        let mut display = match CH170Display::new(&HidConfig::default(), &Shutdown::new()) {
             Ok(d) => {
                 println!("✓ Successfully connected to CH170 display");
                 d
@@ -300,7 +522,7 @@ mod tests {
         ];
 
         for (mode_name, mode) in &modes {
-            display.mode = *mode;
+            display.set_mode(*mode);
             println!("Testing {} mode...", mode_name);
 
             match display.update(&dummy_readings) {
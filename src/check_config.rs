@@ -0,0 +1,138 @@
+use crate::config::Config;
+use crate::reload::Runtime;
+use crate::schedule;
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+
+/// Validate the config file that would be loaded, printing every unknown key
+/// and invalid value with its line number
+pub fn run(explicit: Option<&Path>) -> Result<()> {
+    let Some(path) = Config::locate(explicit)? else {
+        println!("No config file found, the built-in defaults are used");
+        return Ok(());
+    };
+    let contents = std::fs::read_to_string(&path)
+        .context(format!("Failed to read config file {}", path.display()))?;
+
+    let problems = check(&contents);
+    if problems.is_empty() {
+        println!("{}: OK", path.display());
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{}: {problem}", path.display());
+    }
+    bail!("{} problem(s) found in {}", problems.len(), path.display());
+}
+
+/// Problems in a config file, empty if it is valid
+fn check(contents: &str) -> Vec<String> {
+    let (config, unknown_keys) = match Config::parse(contents) {
+        Ok(parsed) => parsed,
+        // Deserialize errors already point at the offending line
+        Err(err) => return vec![err.to_string().trim_end().to_string()],
+    };
+
+    let mut problems: Vec<String> = unknown_keys
+        .into_iter()
+        .map(|unknown| match unknown.line {
+            Some(line) => format!("line {line}: unknown key `{}`", unknown.key),
+            None => format!("unknown key `{}`", unknown.key),
+        })
+        .collect();
+
+    // Settings that parse but are rejected when the components are built
+    if let Err(err) = Runtime::new(&config) {
+        // Every window would fail the same way
+        problems.push(format!("{err:#}"));
+        return problems;
+    }
+    for (index, window) in config.schedule.windows.iter().enumerate() {
+        if let Err(err) = Runtime::build(&config, None, Some(index)) {
+            problems.push(format!(
+                "schedule window {}: {err:#}",
+                schedule::label(window)
+            ));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_config() {
+        let problems = check(
+            r#"
+[display]
+values = "peak"
+rotation = [{ mode = "gpu", seconds = 10 }, { mode = "cpu-fan", seconds = 3 }]
+
+[filters]
+cpu_temp = [{ type = "ema", alpha = 0.3 }]
+"#,
+        );
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let problems = check(
+            r#"
+[lhm]
+url = "http://127.0.0.1:8085/data.json"
+polling_period = 500
+
+[hid]
+vendor_id = 13875
+"#,
+        );
+        assert_eq!(problems, ["line 4: unknown key `lhm.polling_period`"]);
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let problems = check("[display]\nvalues = \"average\"\n");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("line 2"), "{problems:?}");
+
+        let problems = check("[filters]\ncpu_temp = [{ type = \"ema\", alpha = 2.0 }]\n");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("line 2"), "{problems:?}");
+        assert!(problems[0].contains("alpha"), "{problems:?}");
+
+        let problems = check("[lhm]\ntimeout_ms = 100\n\n[oversampling]\nrate_hz = 100000\n");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("line 5: "), "{problems:?}");
+        assert!(problems[0].contains("rate_hz"), "{problems:?}");
+
+        let problems = check("[display]\nrotation = []\n");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("line 2: "), "{problems:?}");
+        assert!(problems[0].contains("rotation"), "{problems:?}");
+
+        let problems = check(
+            "[[profiles]]\nname = \"gaming\"\nprocesses = [\"game\"]\n\
+             [profiles.display]\nrotation = []\n",
+        );
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("line 5: "), "{problems:?}");
+
+        let problems = check(
+            "[[profiles]]\nname = \"gaming\"\nprocesses = [\"game\"]\n\
+             [[profiles.display.rules]]\nmode = \"gpu\"\nwhen = \"gpu_usage >\"\n",
+        );
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with("Invalid profile gaming"),
+            "{problems:?}"
+        );
+
+        let problems = check("[schedule]\nwindows = [{ start = \"22:00\", end = \"22:00\" }]\n");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("line 2: "), "{problems:?}");
+        assert!(problems[0].contains("22:00-22:00"), "{problems:?}");
+    }
+}
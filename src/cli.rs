@@ -0,0 +1,136 @@
+use crate::ch_170::DisplayMode;
+use crate::sensor_readings::Metric;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use tracing::Level;
+
+/// DeepCool CH170 digital display controller
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Config file to use instead of the usual lookup
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Most verbose level logged: error, warn, info, debug or trace
+    #[arg(long, global = true, default_value_t = Level::INFO)]
+    pub log_level: Level,
+
+    /// Serial number or HID path of the display, when several are connected
+    #[arg(long, global = true)]
+    pub device: Option<String>,
+
+    /// Print frames as hex instead of writing them to the display
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Update the display from LibreHardwareMonitor until stopped (default)
+    Run,
+    /// List connected CH170 displays
+    ListDevices,
+    /// Print the LibreHardwareMonitor sensor tree
+    ListSensors {
+        /// Also print a `[sensors]` config block for the detected sensors
+        #[arg(long)]
+        emit_config: bool,
+    },
+    /// Write a single frame with the given values
+    Send(SendArgs),
+    /// Sweep test values through every display mode
+    TestPattern,
+    /// Validate the config file, reporting unknown keys and invalid values
+    CheckConfig,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct SendArgs {
+    /// Display mode
+    #[arg(long, value_enum, default_value = "cpu-frequency")]
+    pub mode: DisplayMode,
+
+    /// Values as `metric=value`, e.g. `cpu_temp=65 cpu_usage=40`. Metrics not
+    /// given are sent as 0.
+    #[arg(value_parser = parse_metric_value)]
+    pub values: Vec<(Metric, f64)>,
+}
+
+fn parse_metric_value(arg: &str) -> Result<(Metric, f64), String> {
+    let (name, value) = arg
+        .split_once('=')
+        .ok_or_else(|| "expected `metric=value`".to_string())?;
+    let metric = Metric::from_name(name.trim()).ok_or_else(|| {
+        let names: Vec<_> = Metric::ALL.iter().map(Metric::name).collect();
+        format!(
+            "unknown metric `{name}`, expected one of {}",
+            names.join(", ")
+        )
+    })?;
+    let value = value
+        .trim()
+        .parse()
+        .map_err(|err| format!("invalid value `{value}`: {err}"))?;
+    Ok((metric, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_command_and_global_flags() {
+        let cli = Cli::try_parse_from(["deepcool-ch170"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.log_level, Level::INFO);
+
+        let cli = Cli::try_parse_from([
+            "deepcool-ch170",
+            "list-sensors",
+            "--emit-config",
+            "--config",
+            "ch170.toml",
+            "--log-level",
+            "debug",
+            "--dry-run",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::ListSensors { emit_config: true })
+        ));
+        assert_eq!(cli.config, Some(PathBuf::from("ch170.toml")));
+        assert_eq!(cli.log_level, Level::DEBUG);
+        assert!(cli.dry_run);
+    }
+
+    #[test]
+    fn test_send_values() {
+        let cli = Cli::try_parse_from([
+            "deepcool-ch170",
+            "send",
+            "--mode",
+            "gpu",
+            "gpu_temp=71.5",
+            "gpu_usage = 98",
+        ])
+        .unwrap();
+        let Some(Command::Send(args)) = cli.command else {
+            panic!("expected send, got {:?}", cli.command);
+        };
+        assert_eq!(args.mode, DisplayMode::Gpu);
+        assert_eq!(
+            args.values,
+            [(Metric::GpuTemp, 71.5), (Metric::GpuUsage, 98.0)]
+        );
+
+        for invalid in ["gpu_temp", "gpu_tmp=70", "gpu_temp=hot"] {
+            assert!(Cli::try_parse_from(["deepcool-ch170", "send", invalid]).is_err());
+        }
+        assert!(Cli::try_parse_from(["deepcool-ch170", "send", "--mode", "psu"]).is_err());
+    }
+}
@@ -0,0 +1,1273 @@
+use crate::ch_170::DisplayMode;
+use crate::schedule::Day;
+use crate::sensor_readings::Metric;
+use anyhow::{Context, Result, bail, ensure};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::num::NonZeroU32;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::de::{DeTable, DeValue};
+use tracing::{info, warn};
+
+// Configuration Constants
+const CONFIG_FILE_NAME: &str = "deepcool-ch170.toml";
+const CONFIG_PATH_ENV: &str = "CH170_CONFIG";
+const CONFIG_DIR_NAME: &str = "deepcool-ch170";
+const USER_CONFIG_FILE_NAME: &str = "config.toml";
+const DEFAULT_LHM_URL: &str = "http://127.0.0.1:8085/data.json";
+const DEFAULT_LHM_TIMEOUT_MS: u64 = 100;
+const DEFAULT_POLLING_PERIOD_MS: u32 = 1000;
+const DEFAULT_MODE_SECONDS: u32 = 5;
+const DEFAULT_RULE_DWELL_SECONDS: u32 = 5;
+const DEEPCOOL_VENDOR_ID: u16 = 13875;
+const CH170_PRODUCT_ID: u16 = 19;
+const DEFAULT_MAX_CONNECTION_RETRIES: u32 = 3;
+const DEFAULT_RETRY_DELAY_SECS: u64 = 5;
+const DEFAULT_STALE_MAX_AGE_MS: u64 = 5000;
+const DEFAULT_PLAUSIBILITY_PERSIST: u32 = 3;
+const MAX_OVERSAMPLING_RATE_HZ: u32 = 100;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub lhm: LhmConfig,
+    #[serde(default)]
+    pub hid: HidConfig,
+    #[serde(default)]
+    pub sensors: SensorMapping,
+    #[serde(default)]
+    pub devices: DeviceSelection,
+    /// How values from several matching devices are combined, per metric
+    #[serde(default)]
+    pub aggregation: HashMap<Metric, Aggregation>,
+    #[serde(default)]
+    pub display: DisplayConfig,
+    #[serde(default)]
+    pub stale: StaleConfig,
+    /// Rules for dropping glitched samples, per metric
+    #[serde(default)]
+    pub plausibility: HashMap<Metric, PlausibilityRule>,
+    /// Smoothing filters applied in order to each metric before display
+    #[serde(default)]
+    pub filters: HashMap<Metric, Vec<FilterConfig>>,
+    #[serde(default)]
+    pub oversampling: OversamplingConfig,
+    /// Named expressions over metrics and raw LHM sensors
+    #[serde(default)]
+    pub derived: BTreeMap<String, String>,
+    /// Display slots showing a derived metric instead of their own
+    #[serde(default)]
+    pub slots: HashMap<Metric, String>,
+    /// Overrides applied while matching processes run, the first listed wins
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+}
+
+impl Config {
+    /// Load the configuration from the first file found by [`Config::locate`],
+    /// falling back to the built-in defaults if there is none
+    pub fn load(explicit: Option<&Path>) -> Result<Self> {
+        match Self::locate(explicit)? {
+            Some(path) => Self::from_file(&path),
+            None => {
+                info!("No config file found, using defaults");
+                Ok(Self::default())
+            }
+        }
+    }
+
+    /// Find the config file: the `--config` path, `$CH170_CONFIG`,
+    /// `deepcool-ch170.toml` in the working directory, then
+    /// `deepcool-ch170/config.toml` in the user config directory
+    ///
+    /// Explicitly given paths must exist.
+    pub fn locate(explicit: Option<&Path>) -> Result<Option<PathBuf>> {
+        let explicit = explicit
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::var_os(CONFIG_PATH_ENV).map(PathBuf::from));
+        if let Some(path) = explicit {
+            ensure!(
+                path.exists(),
+                "Config file {} does not exist",
+                path.display()
+            );
+            return Ok(Some(path));
+        }
+
+        let candidates = std::iter::once(PathBuf::from(CONFIG_FILE_NAME)).chain(
+            user_config_dir().map(|dir| dir.join(CONFIG_DIR_NAME).join(USER_CONFIG_FILE_NAME)),
+        );
+        Ok(candidates.into_iter().find(|path| path.exists()))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .context(format!("Failed to read config file {}", path.display()))?;
+        let config = Self::from_toml(&contents)
+            .context(format!("Failed to parse config file {}", path.display()))?;
+
+        info!(path = %path.display(), "Loaded config file");
+        Ok(config)
+    }
+
+    /// Parse a config, logging a warning for every key that isn't a setting
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let (config, unknown_keys) = Self::parse(contents)?;
+        for unknown in unknown_keys {
+            warn!(key = unknown.key, line = ?unknown.line, "Unknown config key ignored");
+        }
+        Ok(config)
+    }
+
+    /// Parse a config, also returning the keys that aren't settings
+    pub fn parse(contents: &str) -> Result<(Self, Vec<UnknownKey>)> {
+        let mut ignored = Vec::new();
+        let deserializer = toml::de::Deserializer::parse(contents)?;
+        let config: Self = serde_ignored::deserialize(deserializer, |path| {
+            ignored.push(path_segments(&path));
+        })?;
+
+        let root = DeTable::parse(contents)?;
+        let line = |segments: &[String]| {
+            table_key_span(root.get_ref(), segments)
+                .map(|span| contents[..span.start].matches('\n').count() + 1)
+        };
+        if let Err(invalid) = config.validate() {
+            match line(&invalid.key) {
+                Some(line) => bail!("line {line}: {}", invalid.message),
+                None => bail!("{}", invalid.message),
+            }
+        }
+
+        let mut unknown_keys: Vec<UnknownKey> = ignored
+            .into_iter()
+            .map(|segments| UnknownKey {
+                key: segments.join("."),
+                line: line(&segments),
+            })
+            .collect();
+        unknown_keys.sort_by_key(|unknown| unknown.line);
+        Ok((config, unknown_keys))
+    }
+
+    /// Check the settings that depend on each other or on their neighbours in
+    /// a list, which a single value's deserializer can't see
+    fn validate(&self) -> std::result::Result<(), InvalidValue> {
+        if let Some(rate_hz) = self.oversampling.rate_hz {
+            // A sample period shorter than the LHM timeout would pile requests up
+            let max_rate_hz =
+                (1000.0 / self.lhm.timeout_ms as f64).min(MAX_OVERSAMPLING_RATE_HZ as f64);
+            if rate_hz.get() as f64 > max_rate_hz {
+                return Err(InvalidValue::new(
+                    &["oversampling", "rate_hz"],
+                    format!(
+                        "Oversampling rate_hz must be at most {} with a {} ms LHM timeout",
+                        max_rate_hz.floor(),
+                        self.lhm.timeout_ms
+                    ),
+                ));
+            }
+        }
+        self.display.validate(&["display"])?;
+
+        for metric in Metric::ALL {
+            if let Some(rule) = self.plausibility.get(&metric)
+                && rule.persist < 2
+            {
+                return Err(InvalidValue::new(
+                    &["plausibility", metric.name(), "persist"],
+                    format!(
+                        "Plausibility persist for {} must be at least 2, otherwise every jump is accepted",
+                        metric.name()
+                    ),
+                ));
+            }
+        }
+
+        let mut names = HashSet::new();
+        for (index, profile) in self.profiles.iter().enumerate() {
+            let index = index.to_string();
+            if !names.insert(profile.name.as_str()) {
+                return Err(InvalidValue::new(
+                    &["profiles", &index, "name"],
+                    format!("Duplicate profile name `{}`", profile.name),
+                ));
+            }
+            if profile.processes.is_empty() {
+                return Err(InvalidValue::new(
+                    &["profiles", &index, "processes"],
+                    format!("Profile `{}` has no processes to match", profile.name),
+                ));
+            }
+            if let Some(display) = &profile.display {
+                display.validate(&["profiles", &index, "display"])?;
+            }
+        }
+
+        for (index, window) in self.schedule.windows.iter().enumerate() {
+            let index = index.to_string();
+            if window.start == window.end {
+                return Err(InvalidValue::new(
+                    &["schedule", "windows", &index, "start"],
+                    format!(
+                        "Schedule window {} has the same start and end",
+                        crate::schedule::label(window)
+                    ),
+                ));
+            }
+            if window.days.is_empty() {
+                return Err(InvalidValue::new(
+                    &["schedule", "windows", &index, "days"],
+                    format!(
+                        "Schedule window {} has no days",
+                        crate::schedule::label(window)
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// This config with the named profile's overrides applied, or unchanged
+    /// for `None`
+    pub fn with_profile(&self, name: Option<&str>) -> Config {
+        let mut config = self.clone();
+        let Some(profile) = self
+            .profiles
+            .iter()
+            .find(|profile| Some(profile.name.as_str()) == name)
+        else {
+            return config;
+        };
+        if let Some(display) = &profile.display {
+            config.display = display.clone();
+        }
+        if let Some(sensors) = &profile.sensors {
+            config.sensors = sensors.clone();
+        }
+        if let Some(polling_period_ms) = profile.polling_period_ms {
+            config.lhm.polling_period_ms = polling_period_ms;
+        }
+        config
+    }
+
+    /// This config with a schedule window's overrides applied
+    pub fn with_window(&self, window: &ScheduleWindow) -> Config {
+        let mut config = self.clone();
+        if let Some(polling_period_ms) = window.polling_period_ms {
+            config.lhm.polling_period_ms = polling_period_ms;
+        }
+        // A forced mode wins over the rotation and the mode rules alike
+        if let Some(mode) = window.mode {
+            config.display.fixed_mode = Some(mode);
+            config.display.rules.clear();
+        }
+        config
+    }
+
+    pub fn aggregation(&self, metric: Metric) -> Aggregation {
+        self.aggregation.get(&metric).copied().unwrap_or_default()
+    }
+}
+
+/// Settings that replace the base config while one of `processes` runs
+///
+/// `display` and `sensors` replace the whole section.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    /// Process names, matched case-insensitively and without `.exe`
+    pub processes: Vec<String>,
+    pub display: Option<DisplayConfig>,
+    pub sensors: Option<SensorMapping>,
+    pub polling_period_ms: Option<NonZeroU32>,
+}
+
+/// Time-of-day windows that change what the display does
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    /// IANA time zone the windows are in, the system time zone if unset
+    pub time_zone: Option<String>,
+    /// The first listed window covering the current time applies
+    pub windows: Vec<ScheduleWindow>,
+}
+
+/// Settings that apply from `start` until `end` local time on `days`
+///
+/// A window ending before it starts runs past midnight into the next day.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ScheduleWindow {
+    #[serde(default = "default_days")]
+    pub days: Vec<Day>,
+    pub start: jiff::civil::Time,
+    pub end: jiff::civil::Time,
+    /// Show zero readings once and stop reading sensors, with the backlight
+    /// left on as the protocol has no command to turn it off
+    #[serde(default)]
+    pub zeros: bool,
+    pub polling_period_ms: Option<NonZeroU32>,
+    /// Show only this mode, ignoring the rotation and mode rules
+    pub mode: Option<DisplayMode>,
+}
+
+fn default_days() -> Vec<Day> {
+    Day::ALL.to_vec()
+}
+
+/// A setting rejected by [`Config::validate`], and the key it is at
+#[derive(Debug)]
+struct InvalidValue {
+    key: Vec<String>,
+    message: String,
+}
+
+impl InvalidValue {
+    fn new(key: &[&str], message: String) -> Self {
+        Self {
+            key: key.iter().map(|segment| segment.to_string()).collect(),
+            message,
+        }
+    }
+}
+
+/// A config key that doesn't match any setting
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnknownKey {
+    pub key: String,
+    pub line: Option<usize>,
+}
+
+fn path_segments(path: &serde_ignored::Path) -> Vec<String> {
+    let mut segments = match path {
+        serde_ignored::Path::Root => return Vec::new(),
+        serde_ignored::Path::Seq { parent, .. }
+        | serde_ignored::Path::Map { parent, .. }
+        | serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => path_segments(parent),
+    };
+    match path {
+        serde_ignored::Path::Seq { index, .. } => segments.push(index.to_string()),
+        serde_ignored::Path::Map { key, .. } => segments.push(key.clone()),
+        _ => {}
+    }
+    segments
+}
+
+/// Source span of the key at `segments` below `table`
+fn table_key_span(table: &DeTable, segments: &[String]) -> Option<Range<usize>> {
+    let (first, rest) = segments.split_first()?;
+    let (key, value) = table
+        .iter()
+        .find(|(key, _)| key.get_ref().as_ref() == first.as_str())?;
+    if rest.is_empty() {
+        return Some(key.span());
+    }
+    match value.get_ref() {
+        DeValue::Table(table) => table_key_span(table, rest),
+        DeValue::Array(items) => {
+            let (index, rest) = rest.split_first()?;
+            match items.get(index.parse::<usize>().ok()?)?.get_ref() {
+                DeValue::Table(table) => table_key_span(table, rest),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Per-user config directory: `$XDG_CONFIG_HOME`, else `%APPDATA%` on Windows
+/// or `~/.config` elsewhere
+fn user_config_dir() -> Option<PathBuf> {
+    let from_env = |name: &str| {
+        std::env::var_os(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    if let Some(dir) = from_env("XDG_CONFIG_HOME") {
+        return Some(dir);
+    }
+    if cfg!(windows) {
+        from_env("APPDATA")
+    } else {
+        from_env("HOME").map(|home| home.join(".config"))
+    }
+}
+
+/// LibreHardwareMonitor Remote Web Server connection
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LhmConfig {
+    pub url: String,
+    pub timeout_ms: u64,
+    /// How often sensors are read and the display refreshed
+    pub polling_period_ms: NonZeroU32,
+    /// Basic-auth credentials, for web servers with a password set
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for LhmConfig {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_LHM_URL.to_string(),
+            timeout_ms: DEFAULT_LHM_TIMEOUT_MS,
+            polling_period_ms: NonZeroU32::new(DEFAULT_POLLING_PERIOD_MS).unwrap(),
+            username: None,
+            password: None,
+        }
+    }
+}
+
+/// USB HID connection to the CH170
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HidConfig {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// Serial number or HID path of the display to use when several are connected
+    pub device: Option<String>,
+    pub max_connection_retries: NonZeroU32,
+    pub retry_delay_secs: u64,
+}
+
+impl Default for HidConfig {
+    fn default() -> Self {
+        Self {
+            vendor_id: DEEPCOOL_VENDOR_ID,
+            product_id: CH170_PRODUCT_ID,
+            device: None,
+            max_connection_retries: NonZeroU32::new(DEFAULT_MAX_CONNECTION_RETRIES).unwrap(),
+            retry_delay_secs: DEFAULT_RETRY_DELAY_SECS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    pub values: DisplayValues,
+    /// Modes shown in turn, each for its own duration. Modes left out are never shown.
+    pub rotation: Vec<ModeSlot>,
+    /// Show only this mode instead of rotating
+    pub fixed_mode: Option<DisplayMode>,
+    /// Pass over modes none of whose sensors currently have data
+    pub skip_unavailable: bool,
+    /// Conditions that show a mode in place of the rotation while they hold
+    pub rules: Vec<ModeRule>,
+}
+
+impl DisplayConfig {
+    /// `key` is where this table is in the config file
+    fn validate(&self, key: &[&str]) -> std::result::Result<(), InvalidValue> {
+        if self.fixed_mode.is_none() && self.rotation.is_empty() {
+            return Err(InvalidValue::new(
+                &[key, &["rotation"]].concat(),
+                "Mode rotation is empty, list at least one mode or set fixed_mode".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        let seconds = NonZeroU32::new(DEFAULT_MODE_SECONDS).unwrap();
+        Self {
+            values: DisplayValues::default(),
+            rotation: DisplayMode::ALL
+                .into_iter()
+                .map(|mode| ModeSlot { mode, seconds })
+                .collect(),
+            fixed_mode: None,
+            skip_unavailable: true,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// Show `mode` while the `when` expression has held for `for_seconds`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ModeRule {
+    pub mode: DisplayMode,
+    pub when: String,
+    #[serde(default)]
+    pub for_seconds: u32,
+    /// Higher priorities win when several rules match
+    #[serde(default)]
+    pub priority: i32,
+    /// Once shown, the mode stays at least this long unless a higher priority
+    /// rule matches
+    #[serde(default = "default_rule_dwell_seconds")]
+    pub min_dwell_seconds: u32,
+}
+
+fn default_rule_dwell_seconds() -> u32 {
+    DEFAULT_RULE_DWELL_SECONDS
+}
+
+/// One step of the mode rotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct ModeSlot {
+    pub mode: DisplayMode,
+    pub seconds: NonZeroU32,
+}
+
+/// Which value of each metric is sent to the display
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DisplayValues {
+    /// The latest reading
+    #[default]
+    Current,
+    /// The highest reading seen, for inspecting a benchmark run afterwards
+    Peak,
+}
+
+/// When a metric that stopped updating is considered stale and what is shown then
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct StaleConfig {
+    pub max_age_ms: u64,
+    pub policy: StalePolicy,
+}
+
+impl Default for StaleConfig {
+    fn default() -> Self {
+        Self {
+            max_age_ms: DEFAULT_STALE_MAX_AGE_MS,
+            policy: StalePolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StalePolicy {
+    /// Keep showing the last value, with a warning logged
+    #[default]
+    Hold,
+    /// Show 0
+    Zero,
+    /// Mark the value unavailable, which the display shows as 0 but mode rules,
+    /// filters and derived metrics treat as missing
+    Blank,
+}
+
+/// LibreHardwareMonitor `SensorId`s for each displayed metric
+///
+/// Metrics without an explicit ID fall back to the selected preset, if any, and
+/// are otherwise discovered from the LHM sensor tree. A metric can list several
+/// IDs, e.g. one per CPU socket, which are combined by its [`Aggregation`].
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SensorMapping {
+    pub preset: Option<SensorPreset>,
+    pub cpu_temp: Option<SensorIds>,
+    pub cpu_power: Option<SensorIds>,
+    pub cpu_usage: Option<SensorIds>,
+    pub cpu_freq: Option<SensorIds>,
+    pub cpu_cooler_rpm: Option<SensorIds>,
+    pub gpu_temp: Option<SensorIds>,
+    pub gpu_power: Option<SensorIds>,
+    pub gpu_usage: Option<SensorIds>,
+    pub gpu_freq: Option<SensorIds>,
+    /// Linear corrections for sensors that read off, per metric
+    pub calibration: HashMap<Metric, Calibration>,
+}
+
+impl SensorMapping {
+    /// Configured sensor IDs for `metric`, empty if it should be discovered
+    pub fn sensor_ids(&self, metric: Metric) -> Vec<&str> {
+        let explicit = match metric {
+            Metric::CpuTemp => &self.cpu_temp,
+            Metric::CpuPower => &self.cpu_power,
+            Metric::CpuUsage => &self.cpu_usage,
+            Metric::CpuFreq => &self.cpu_freq,
+            Metric::CpuCoolerRpm => &self.cpu_cooler_rpm,
+            Metric::GpuTemp => &self.gpu_temp,
+            Metric::GpuPower => &self.gpu_power,
+            Metric::GpuUsage => &self.gpu_usage,
+            Metric::GpuFreq => &self.gpu_freq,
+        };
+
+        match explicit {
+            Some(SensorIds::One(sensor_id)) => vec![sensor_id.as_str()],
+            Some(SensorIds::Many(sensor_ids)) => sensor_ids.iter().map(String::as_str).collect(),
+            None => self
+                .preset
+                .and_then(|preset| preset.sensor_id(metric))
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SensorIds {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Restricts automatic discovery to one CPU or GPU on multi-device machines
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DeviceSelection {
+    pub cpu: Option<DeviceSelector>,
+    pub gpu: Option<DeviceSelector>,
+}
+
+impl DeviceSelection {
+    /// Selected device for the hardware class `metric` is read from, if any
+    pub fn selector(&self, metric: Metric) -> Option<&DeviceSelector> {
+        match metric {
+            Metric::CpuTemp | Metric::CpuPower | Metric::CpuUsage | Metric::CpuFreq => {
+                self.cpu.as_ref()
+            }
+            Metric::GpuTemp | Metric::GpuPower | Metric::GpuUsage | Metric::GpuFreq => {
+                self.gpu.as_ref()
+            }
+            Metric::CpuCoolerRpm => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum DeviceSelector {
+    /// Position among devices of the same class, in LHM order
+    Index(usize),
+    /// Case-insensitive substring of the hardware name
+    Name(String),
+}
+
+/// Linear correction `value * multiplier + offset`, optionally clamped to `min`..`max`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Calibration {
+    pub offset: f64,
+    pub multiplier: f64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            offset: 0.0,
+            multiplier: 1.0,
+            min: None,
+            max: None,
+        }
+    }
+}
+
+impl Calibration {
+    pub fn apply(&self, value: f64) -> f64 {
+        let mut value = value * self.multiplier + self.offset;
+        if let Some(min) = self.min {
+            value = value.max(min);
+        }
+        if let Some(max) = self.max {
+            value = value.min(max);
+        }
+        value
+    }
+}
+
+/// How a metric combines several values, from multiple devices or samples
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Aggregation {
+    /// Only the first device or sample
+    #[default]
+    First,
+    Sum,
+    Min,
+    Max,
+    Average,
+}
+
+impl Aggregation {
+    pub fn apply(&self, values: &[f64]) -> Option<f64> {
+        let first = *values.first()?;
+        Some(match self {
+            Aggregation::First => first,
+            Aggregation::Sum => values.iter().sum(),
+            Aggregation::Min => values.iter().copied().fold(first, f64::min),
+            Aggregation::Max => values.iter().copied().fold(first, f64::max),
+            Aggregation::Average => values.iter().sum::<f64>() / values.len() as f64,
+        })
+    }
+}
+
+/// Sampling LHM faster than the display refreshes
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OversamplingConfig {
+    /// Sensor samples per second, once per display refresh if unset
+    pub rate_hz: Option<NonZeroU32>,
+    /// How the samples since the last refresh are combined, per metric
+    pub aggregation: HashMap<Metric, Aggregation>,
+}
+
+impl OversamplingConfig {
+    /// Configured policy for `metric`, by default the highest temperature and
+    /// the average of everything else
+    pub fn aggregation(&self, metric: Metric) -> Aggregation {
+        self.aggregation
+            .get(&metric)
+            .copied()
+            .unwrap_or(match metric {
+                Metric::CpuTemp | Metric::GpuTemp => Aggregation::Max,
+                _ => Aggregation::Average,
+            })
+    }
+}
+
+/// Bounds and maximum rate of change, in units per second, for a metric's samples
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PlausibilityRule {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub max_rate: Option<f64>,
+    /// Consecutive samples after which a jump faster than `max_rate` is accepted
+    pub persist: u32,
+}
+
+impl Default for PlausibilityRule {
+    fn default() -> Self {
+        Self {
+            min: None,
+            max: None,
+            max_rate: None,
+            persist: DEFAULT_PLAUSIBILITY_PERSIST,
+        }
+    }
+}
+
+/// One smoothing stage of a metric's filter chain
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "FilterTable")]
+pub enum FilterConfig {
+    /// Exponential moving average, `alpha` in (0, 1] weighs the newest sample
+    Ema { alpha: f64 },
+    /// Median of the last `window` samples
+    Median { window: usize },
+    /// Only follow the input once it moves more than `width` from the shown value
+    Deadband { width: f64 },
+}
+
+/// How a [`FilterConfig`] is written, as a plain table rather than an
+/// internally tagged enum, whose buffering would hide unknown keys
+#[derive(Deserialize)]
+struct FilterTable {
+    #[serde(rename = "type")]
+    kind: FilterKind,
+    alpha: Option<f64>,
+    window: Option<usize>,
+    width: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum FilterKind {
+    Ema,
+    Median,
+    Deadband,
+}
+
+impl FilterKind {
+    fn name(&self) -> &'static str {
+        match self {
+            FilterKind::Ema => "ema",
+            FilterKind::Median => "median",
+            FilterKind::Deadband => "deadband",
+        }
+    }
+}
+
+impl TryFrom<FilterTable> for FilterConfig {
+    type Error = String;
+
+    fn try_from(table: FilterTable) -> std::result::Result<Self, String> {
+        let settings = [
+            ("alpha", table.alpha.is_some(), FilterKind::Ema),
+            ("window", table.window.is_some(), FilterKind::Median),
+            ("width", table.width.is_some(), FilterKind::Deadband),
+        ];
+        for (key, set, kind) in settings {
+            if set && kind != table.kind {
+                return Err(format!(
+                    "`{key}` does not apply to {} filters",
+                    table.kind.name()
+                ));
+            }
+        }
+
+        let missing = |key: &str| format!("missing field `{key}`");
+        Ok(match table.kind {
+            FilterKind::Ema => {
+                let alpha = table.alpha.ok_or_else(|| missing("alpha"))?;
+                if !(alpha > 0.0 && alpha <= 1.0) {
+                    return Err(format!("EMA alpha must be in (0, 1], got {alpha}"));
+                }
+                FilterConfig::Ema { alpha }
+            }
+            FilterKind::Median => {
+                let window = table.window.ok_or_else(|| missing("window"))?;
+                if window == 0 {
+                    return Err("Median window must be at least 1".to_string());
+                }
+                FilterConfig::Median { window }
+            }
+            FilterKind::Deadband => {
+                let width = table.width.ok_or_else(|| missing("width"))?;
+                if width.is_nan() || width < 0.0 {
+                    return Err(format!("Deadband width must not be negative, got {width}"));
+                }
+                FilterConfig::Deadband { width }
+            }
+        })
+    }
+}
+
+/// Named sets of sensor IDs for known machines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SensorPreset {
+    /// AMD Ryzen CPU, NVIDIA GPU and a Nuvoton NCT6701D Super I/O fan controller
+    RyzenNvidiaNct6701d,
+}
+
+impl SensorPreset {
+    pub fn sensor_id(&self, metric: Metric) -> Option<&'static str> {
+        match self {
+            SensorPreset::RyzenNvidiaNct6701d => Some(match metric {
+                Metric::CpuTemp => "/amdcpu/0/temperature/2",
+                Metric::CpuPower => "/amdcpu/0/power/0",
+                Metric::CpuUsage => "/amdcpu/0/load/0",
+                Metric::CpuFreq => "/amdcpu/0/clock/2",
+                Metric::CpuCoolerRpm => "/lpc/nct6701d/0/fan/1",
+                Metric::GpuTemp => "/gpu-nvidia/0/temperature/0",
+                Metric::GpuPower => "/gpu-nvidia/0/power/0",
+                Metric::GpuUsage => "/gpu-nvidia/0/load/0",
+                Metric::GpuFreq => "/gpu-nvidia/0/clock/0",
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oversampling_aggregation() {
+        let config = Config::from_toml(
+            r#"
+            [oversampling]
+            rate_hz = 10
+            aggregation = { cpu_power = "max", gpu_temp = "average" }
+            "#,
+        )
+        .unwrap();
+        let oversampling = &config.oversampling;
+        assert_eq!(oversampling.rate_hz, NonZeroU32::new(10));
+        assert_eq!(oversampling.aggregation(Metric::CpuTemp), Aggregation::Max);
+        assert_eq!(oversampling.aggregation(Metric::CpuPower), Aggregation::Max);
+        assert_eq!(
+            oversampling.aggregation(Metric::CpuUsage),
+            Aggregation::Average
+        );
+        assert_eq!(
+            oversampling.aggregation(Metric::GpuTemp),
+            Aggregation::Average
+        );
+    }
+
+    #[test]
+    fn test_filter_chains() {
+        let config = Config::from_toml(
+            r#"
+            [filters]
+            cpu_temp = [{ type = "median", window = 5 }, { type = "deadband", width = 0.5 }]
+            gpu_power = [{ type = "ema", alpha = 0.3 }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.filters[&Metric::CpuTemp],
+            [
+                FilterConfig::Median { window: 5 },
+                FilterConfig::Deadband { width: 0.5 }
+            ]
+        );
+        assert_eq!(
+            config.filters[&Metric::GpuPower],
+            [FilterConfig::Ema { alpha: 0.3 }]
+        );
+        assert!(Config::from_toml("[filters]\ncpu_temp = [{ type = \"kalman\" }]").is_err());
+        assert!(Config::from_toml("[filters]\ncpu_temp = [{ type = \"ema\" }]").is_err());
+        let err =
+            Config::parse("[filters]\ncpu_temp = [{ type = \"ema\", alpha = 0.3, window = 3 }]")
+                .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("`window` does not apply to ema filters"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_unknown_keys_reported_with_line() {
+        let (config, unknown_keys) = Config::parse(
+            "[display]\nvalues = \"peak\"\nvaluse = \"current\"\n\n[dispaly]\nvalues = 1\n",
+        )
+        .unwrap();
+        assert_eq!(config.display.values, DisplayValues::Peak);
+        assert_eq!(
+            unknown_keys,
+            [
+                UnknownKey {
+                    key: "display.valuse".to_string(),
+                    line: Some(3)
+                },
+                UnknownKey {
+                    key: "dispaly".to_string(),
+                    line: Some(5)
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_keys_in_nested_tables() {
+        let (_, unknown_keys) = Config::parse(
+            r#"
+[sensors.calibration]
+cpu_temp = { ofset = 1.0 }
+
+[plausibility]
+cpu_temp = { max_rate = 5, persits = 3 }
+
+[filters]
+cpu_temp = [{ type = "ema", alpha = 0.3, alpah = 0.5 }]
+
+[display]
+rotation = [{ mode = "gpu", seconds = 5, secs = 5 }]
+
+[[display.rules]]
+mode = "gpu"
+when = "gpu_usage > 50"
+for = 5
+
+[[profiles]]
+name = "gaming"
+processes = ["game"]
+polling_ms = 500
+
+[schedule]
+windows = [{ start = "22:00", end = "07:00", blnak = true }]
+"#,
+        )
+        .unwrap();
+        let keys: Vec<_> = unknown_keys
+            .iter()
+            .map(|unknown| unknown.key.as_str())
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "sensors.calibration.cpu_temp.ofset",
+                "plausibility.cpu_temp.persits",
+                "filters.cpu_temp.0.alpah",
+                "display.rotation.0.secs",
+                "display.rules.0.for",
+                "profiles.0.polling_ms",
+                "schedule.windows.0.blnak",
+            ]
+        );
+        let lines: Vec<_> = unknown_keys.iter().map(|unknown| unknown.line).collect();
+        assert_eq!(lines, [3, 6, 9, 12, 17, 22, 25].map(Some));
+    }
+
+    #[test]
+    fn test_invalid_values_reported_with_line() {
+        let err = Config::parse("[lhm]\ntimeout_ms = 100\npolling_period_ms = 0\n").unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{err}");
+
+        let err = Config::parse("[hid]\nvendor_id = \"deepcool\"\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+
+        // Values checked against other settings point at the offending key
+        let err = Config::parse(
+            "[[profiles]]\nname = \"gaming\"\nprocesses = [\"game\"]\n\n\
+             [profiles.display]\nrotation = []\n",
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("line 6: Mode rotation"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_oversampling_rate_limits() {
+        let parse = |rate_hz: u32, timeout_ms: u64| {
+            Config::from_toml(&format!(
+                "[lhm]\ntimeout_ms = {timeout_ms}\n[oversampling]\nrate_hz = {rate_hz}"
+            ))
+        };
+        assert!(parse(0, 100).is_err());
+        // No faster than one request per LHM timeout, and never above 100 Hz
+        assert!(parse(10, 100).is_ok());
+        assert!(parse(11, 100).is_err());
+        assert!(parse(100, 5).is_ok());
+        assert!(parse(100_000, 5).is_err());
+    }
+
+    #[test]
+    fn test_explicit_config_path_must_exist() {
+        let missing = Path::new("/nonexistent/deepcool-ch170.toml");
+        assert!(Config::locate(Some(missing)).is_err());
+        assert!(Config::load(Some(missing)).is_err());
+    }
+
+    #[test]
+    fn test_profile_overrides() {
+        let config = Config::from_toml(
+            r#"
+            [display]
+            values = "peak"
+
+            [sensors]
+            cpu_temp = "/amdcpu/0/temperature/2"
+
+            [[profiles]]
+            name = "gaming"
+            processes = ["Cyberpunk2077.exe"]
+            polling_period_ms = 500
+            display = { fixed_mode = "gpu" }
+            "#,
+        )
+        .unwrap();
+
+        let gaming = config.with_profile(Some("gaming"));
+        assert_eq!(gaming.lhm.polling_period_ms.get(), 500);
+        assert_eq!(gaming.display.fixed_mode, Some(DisplayMode::Gpu));
+        // Sections replace the base one entirely, others are kept
+        assert_eq!(gaming.display.values, DisplayValues::Current);
+        assert!(gaming.sensors.cpu_temp.is_some());
+
+        let base = config.with_profile(None);
+        assert_eq!(base.lhm.polling_period_ms.get(), 1000);
+        assert_eq!(base.display.values, DisplayValues::Peak);
+    }
+
+    #[test]
+    fn test_stale_policy() {
+        let config = Config::default();
+        assert_eq!(config.stale.max_age_ms, 5000);
+        assert_eq!(config.stale.policy, StalePolicy::Hold);
+
+        let config = Config::from_toml(
+            r#"
+            [stale]
+            max_age_ms = 2000
+            policy = "blank"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.stale.max_age_ms, 2000);
+        assert_eq!(config.stale.policy, StalePolicy::Blank);
+    }
+
+    #[test]
+    fn test_default_leaves_metrics_to_discovery() {
+        let config = Config::default();
+        for metric in Metric::ALL {
+            assert!(config.sensors.sensor_ids(metric).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_preset_maps_all_metrics() {
+        let config = Config::from_toml(
+            r#"
+            [sensors]
+            preset = "ryzen-nvidia-nct6701d"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.sensors.sensor_ids(Metric::CpuTemp),
+            ["/amdcpu/0/temperature/2"]
+        );
+        assert_eq!(
+            config.sensors.sensor_ids(Metric::CpuCoolerRpm),
+            ["/lpc/nct6701d/0/fan/1"]
+        );
+    }
+
+    #[test]
+    fn test_explicit_ids_override_preset() {
+        let config = Config::from_toml(
+            r#"
+            [sensors]
+            preset = "ryzen-nvidia-nct6701d"
+            cpu_temp = "/intelcpu/0/temperature/8"
+            cpu_cooler_rpm = "/lpc/nct6798d/0/fan/0"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.sensors.sensor_ids(Metric::CpuTemp),
+            ["/intelcpu/0/temperature/8"]
+        );
+        assert_eq!(
+            config.sensors.sensor_ids(Metric::CpuCoolerRpm),
+            ["/lpc/nct6798d/0/fan/0"]
+        );
+        assert_eq!(
+            config.sensors.sensor_ids(Metric::GpuFreq),
+            ["/gpu-nvidia/0/clock/0"]
+        );
+    }
+
+    #[test]
+    fn test_missing_ids_without_preset_are_discovered() {
+        let config = Config::from_toml(
+            r#"
+            [sensors]
+            gpu_temp = "/gpu-amd/0/temperature/0"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.sensors.sensor_ids(Metric::GpuTemp),
+            ["/gpu-amd/0/temperature/0"]
+        );
+        assert!(config.sensors.sensor_ids(Metric::CpuTemp).is_empty());
+    }
+
+    #[test]
+    fn test_lhm_endpoint() {
+        let config = Config::default();
+        assert_eq!(config.lhm.url, "http://127.0.0.1:8085/data.json");
+        assert_eq!(config.lhm.timeout_ms, 100);
+        assert_eq!(config.lhm.username, None);
+
+        let config = Config::from_toml(
+            r#"
+            [lhm]
+            url = "http://192.168.1.20:8086/data.json"
+            username = "monitor"
+            password = "secret"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.lhm.url, "http://192.168.1.20:8086/data.json");
+        assert_eq!(config.lhm.timeout_ms, 100);
+        assert_eq!(config.lhm.username.as_deref(), Some("monitor"));
+        assert_eq!(config.lhm.password.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn test_display_values() {
+        assert_eq!(Config::default().display.values, DisplayValues::Current);
+
+        let config = Config::from_toml(
+            r#"
+            [display]
+            values = "peak"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.display.values, DisplayValues::Peak);
+    }
+
+    #[test]
+    fn test_calibration() {
+        let config = Config::from_toml(
+            r#"
+            [sensors]
+            cpu_temp = "/amdcpu/0/temperature/2"
+
+            [sensors.calibration]
+            cpu_temp = { offset = -10.0 }
+            cpu_cooler_rpm = { multiplier = 0.5 }
+            gpu_power = { multiplier = 0.95, min = 0.0, max = 450.0 }
+            "#,
+        )
+        .unwrap();
+        let calibration = &config.sensors.calibration;
+        assert_eq!(calibration[&Metric::CpuTemp].apply(78.5), 68.5);
+        assert_eq!(calibration[&Metric::CpuCoolerRpm].apply(2400.0), 1200.0);
+        assert_eq!(calibration[&Metric::GpuPower].apply(200.0), 190.0);
+        assert_eq!(calibration[&Metric::GpuPower].apply(600.0), 450.0);
+        assert_eq!(calibration[&Metric::GpuPower].apply(-5.0), 0.0);
+    }
+
+    #[test]
+    fn test_multiple_sensor_ids_and_aggregation() {
+        let config = Config::from_toml(
+            r#"
+            [sensors]
+            cpu_power = ["/amdcpu/0/power/0", "/amdcpu/1/power/0"]
+
+            [devices]
+            cpu = 1
+            gpu = "RTX 4090"
+
+            [aggregation]
+            cpu_power = "sum"
+            gpu_temp = "max"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.sensors.sensor_ids(Metric::CpuPower),
+            ["/amdcpu/0/power/0", "/amdcpu/1/power/0"]
+        );
+        assert_eq!(config.devices.cpu, Some(DeviceSelector::Index(1)));
+        assert_eq!(
+            config.devices.gpu,
+            Some(DeviceSelector::Name("RTX 4090".to_string()))
+        );
+        assert_eq!(config.aggregation(Metric::CpuPower), Aggregation::Sum);
+        assert_eq!(config.aggregation(Metric::GpuTemp), Aggregation::Max);
+        assert_eq!(config.aggregation(Metric::CpuTemp), Aggregation::First);
+    }
+
+    #[test]
+    fn test_aggregation_policies() {
+        let values = [40.0, 70.0, 55.0];
+        assert_eq!(Aggregation::First.apply(&values), Some(40.0));
+        assert_eq!(Aggregation::Sum.apply(&values), Some(165.0));
+        assert_eq!(Aggregation::Min.apply(&values), Some(40.0));
+        assert_eq!(Aggregation::Max.apply(&values), Some(70.0));
+        assert_eq!(Aggregation::Average.apply(&values), Some(55.0));
+        assert_eq!(Aggregation::Sum.apply(&[]), None);
+    }
+
+    #[test]
+    fn test_unknown_preset_is_rejected() {
+        let result = Config::from_toml(
+            r#"
+            [sensors]
+            preset = "my-machine"
+            "#,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::expression::Expr;
+use crate::sensor_readings::{Metric, SensorReadings};
+use anyhow::{Context, Result, bail};
+use std::collections::{BTreeMap, HashMap};
+use tracing::info;
+
+/// Named expressions shown in display slots in place of the slot's own metric
+pub struct DerivedMetrics {
+    slots: Vec<(Metric, Expr)>,
+}
+
+impl DerivedMetrics {
+    pub fn new(
+        derived: &BTreeMap<String, String>,
+        slots: &HashMap<Metric, String>,
+    ) -> Result<Self> {
+        let mut definitions = HashMap::new();
+        for (name, source) in derived {
+            if Metric::from_name(name).is_some() {
+                bail!("Derived metric `{name}` has the same name as a sensor metric");
+            }
+            let expr = Expr::parse(source).context(format!("Invalid derived metric `{name}`"))?;
+            info!(name, expression = source, "Derived metric");
+            definitions.insert(name.as_str(), expr);
+        }
+
+        let mut assigned = Vec::new();
+        for slot in Metric::ALL {
+            let Some(name) = slots.get(&slot) else {
+                continue;
+            };
+            let Some(expr) = definitions.get(name.as_str()) else {
+                bail!(
+                    "Display slot {} shows unknown derived metric `{name}`",
+                    slot.name()
+                );
+            };
+            info!(slot = slot.name(), derived = name, "Display slot");
+            assigned.push((slot, expr.clone()));
+        }
+        Ok(Self { slots: assigned })
+    }
+
+    /// Whether a derived metric is shown in `slot`
+    pub fn fills_slot(&self, slot: Metric) -> bool {
+        self.slots.iter().any(|(assigned, _)| *assigned == slot)
+    }
+
+    /// The expression shown in `slot`, if any
+    pub fn slot_expr(&self, slot: Metric) -> Option<&Expr> {
+        self.slots
+            .iter()
+            .find(|(assigned, _)| *assigned == slot)
+            .map(|(_, expr)| expr)
+    }
+
+    /// Raw LHM sensors the assigned expressions read
+    pub fn sensor_ids(&self) -> Vec<&str> {
+        self.slots
+            .iter()
+            .flat_map(|(_, expr)| expr.sensor_ids())
+            .collect()
+    }
+
+    /// Evaluate every assigned slot against the same input readings
+    pub fn apply(
+        &self,
+        readings: &SensorReadings,
+        sensors: &HashMap<String, f64>,
+    ) -> SensorReadings {
+        let mut derived = *readings;
+        for (slot, expr) in &self.slots {
+            derived.set(*slot, expr.eval(readings, sensors));
+        }
+        derived
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn derived(toml: &str) -> Result<DerivedMetrics> {
+        let config = Config::from_toml(toml).unwrap();
+        DerivedMetrics::new(&config.derived, &config.slots)
+    }
+
+    #[test]
+    fn test_slots_show_derived_metrics() {
+        let derived = derived(
+            r#"
+            [derived]
+            system_power = "cpu_power + gpu_power"
+            delta_t = "cpu_temp - sensor('/lpc/nct6798d/0/temperature/1')"
+
+            [slots]
+            gpu_power = "system_power"
+            cpu_temp = "delta_t"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(derived.sensor_ids(), ["/lpc/nct6798d/0/temperature/1"]);
+
+        let readings = SensorReadings {
+            cpu_temp: 70.0,
+            cpu_power: 90.0,
+            gpu_power: 200.0,
+            ..SensorReadings::default()
+        };
+        let sensors = HashMap::from([("/lpc/nct6798d/0/temperature/1".to_string(), 30.0)]);
+        let shown = derived.apply(&readings, &sensors);
+        assert_eq!(shown.gpu_power, 290.0);
+        assert_eq!(shown.cpu_temp, 40.0);
+        assert_eq!(shown.cpu_power, 90.0);
+    }
+
+    #[test]
+    fn test_invalid_definitions_rejected() {
+        assert!(derived("[derived]\nbad = \"cpu_power +\"").is_err());
+        assert!(derived("[derived]\ncpu_power = \"gpu_power\"").is_err());
+        assert!(derived("[slots]\ncpu_power = \"missing\"").is_err());
+    }
+}
@@ -0,0 +1,543 @@
+use crate::sensor_readings::{Metric, SensorReadings};
+use anyhow::{Result, bail};
+use std::collections::HashMap;
+
+// Constants
+/// Parentheses, calls and unary operators nested deeper than this are rejected,
+/// as parsing and evaluation recurse once per level
+const MAX_DEPTH: usize = 32;
+/// Bounds the depth of operator chains such as `1 + 1 + ...` the same way
+const MAX_TOKENS: usize = 1000;
+
+/// Arithmetic over metrics and raw LHM sensors, e.g. `cpu_power + gpu_power`
+/// or `cpu_temp - sensor("/lpc/nct6798d/0/temperature/1")`
+///
+/// Comparisons and `&&`, `||`, `!` give 1 for true and 0 for false, for
+/// conditions such as `gpu_usage > 50 && gpu_temp >= 60`. Comparing a blank
+/// value is always false.
+///
+/// Evaluation only reads the values it is given. Results that aren't finite,
+/// such as a division by zero or a missing sensor, come out as NaN (blank).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Metric(Metric),
+    Sensor(String),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Min,
+    Max,
+    Abs,
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Expr> {
+        let tokens = tokenize(source)?;
+        if tokens.len() > MAX_TOKENS {
+            bail!(
+                "Expression has {} tokens, at most {MAX_TOKENS} are allowed",
+                tokens.len()
+            );
+        }
+        let mut parser = Parser {
+            source,
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let expr = parser.or()?;
+        if let Some((token, at)) = parser.tokens.get(parser.pos) {
+            bail!("Unexpected {token} at position {at} in `{source}`");
+        }
+        Ok(expr)
+    }
+
+    /// LHM sensor IDs referenced through `sensor("...")`
+    pub fn sensor_ids(&self) -> Vec<&str> {
+        match self {
+            Expr::Number(_) | Expr::Metric(_) => Vec::new(),
+            Expr::Sensor(sensor_id) => vec![sensor_id.as_str()],
+            Expr::Neg(inner) | Expr::Not(inner) => inner.sensor_ids(),
+            Expr::Binary(_, lhs, rhs) => {
+                let mut ids = lhs.sensor_ids();
+                ids.extend(rhs.sensor_ids());
+                ids
+            }
+            Expr::Call(_, args) => args.iter().flat_map(Expr::sensor_ids).collect(),
+        }
+    }
+
+    pub fn eval(&self, readings: &SensorReadings, sensors: &HashMap<String, f64>) -> f64 {
+        let value = self.eval_raw(readings, sensors);
+        if value.is_finite() { value } else { f64::NAN }
+    }
+
+    /// Evaluate as a condition: true unless zero or blank
+    pub fn is_true(&self, readings: &SensorReadings, sensors: &HashMap<String, f64>) -> bool {
+        truthy(self.eval(readings, sensors))
+    }
+
+    fn eval_raw(&self, readings: &SensorReadings, sensors: &HashMap<String, f64>) -> f64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Metric(metric) => readings.get(*metric),
+            Expr::Sensor(sensor_id) => sensors.get(sensor_id).copied().unwrap_or(f64::NAN),
+            Expr::Neg(inner) => -inner.eval_raw(readings, sensors),
+            Expr::Not(inner) => bool_value(!truthy(inner.eval_raw(readings, sensors))),
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval_raw(readings, sensors);
+                let rhs = rhs.eval_raw(readings, sensors);
+                match op {
+                    BinaryOp::Add => lhs + rhs,
+                    BinaryOp::Sub => lhs - rhs,
+                    BinaryOp::Mul => lhs * rhs,
+                    BinaryOp::Div => lhs / rhs,
+                    // IEEE comparisons are already false for NaN, except `!=`
+                    BinaryOp::Lt => bool_value(lhs < rhs),
+                    BinaryOp::Le => bool_value(lhs <= rhs),
+                    BinaryOp::Gt => bool_value(lhs > rhs),
+                    BinaryOp::Ge => bool_value(lhs >= rhs),
+                    BinaryOp::Eq => bool_value(lhs == rhs),
+                    BinaryOp::Ne => bool_value(!lhs.is_nan() && !rhs.is_nan() && lhs != rhs),
+                    BinaryOp::And => bool_value(truthy(lhs) && truthy(rhs)),
+                    BinaryOp::Or => bool_value(truthy(lhs) || truthy(rhs)),
+                }
+            }
+            Expr::Call(function, args) => {
+                let mut values = args.iter().map(|arg| arg.eval_raw(readings, sensors));
+                match function {
+                    // NaN arguments propagate rather than being skipped
+                    Function::Min => values
+                        .try_fold(f64::INFINITY, |min, value| {
+                            (!value.is_nan()).then(|| min.min(value))
+                        })
+                        .unwrap_or(f64::NAN),
+                    Function::Max => values
+                        .try_fold(f64::NEG_INFINITY, |max, value| {
+                            (!value.is_nan()).then(|| max.max(value))
+                        })
+                        .unwrap_or(f64::NAN),
+                    Function::Abs => values.next().unwrap_or(f64::NAN).abs(),
+                }
+            }
+        }
+    }
+}
+
+fn truthy(value: f64) -> bool {
+    !value.is_nan() && value != 0.0
+}
+
+fn bool_value(value: bool) -> f64 {
+    if value { 1.0 } else { 0.0 }
+}
+
+/// Operators and punctuation, two-character ones first so they match greedily
+const SYMBOLS: [&str; 16] = [
+    "&&", "||", "<=", ">=", "==", "!=", "<", ">", "!", "+", "-", "*", "/", "(", ")", ",",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Str(String),
+    Symbol(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "number {value}"),
+            Token::Ident(name) => write!(f, "`{name}`"),
+            Token::Str(text) => write!(f, "string \"{text}\""),
+            Token::Symbol(symbol) => write!(f, "`{symbol}`"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut text = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+            let Ok(value) = text.parse() else {
+                bail!("Invalid number `{text}` at position {at} in `{source}`");
+            };
+            tokens.push((Token::Number(value), at));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut name = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            tokens.push((Token::Ident(name), at));
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, end)) if end == c => break,
+                    Some((_, other)) => text.push(other),
+                    None => bail!("Unterminated string at position {at} in `{source}`"),
+                }
+            }
+            tokens.push((Token::Str(text), at));
+        } else if let Some(symbol) = SYMBOLS
+            .into_iter()
+            .find(|symbol| source[at..].starts_with(symbol))
+        {
+            tokens.push((Token::Symbol(symbol), at));
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+        } else {
+            bail!("Unexpected character `{c}` at position {at} in `{source}`");
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, lowest precedence first
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// Current nesting of parentheses, calls and unary operators
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek_symbol(&self, symbol: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some((Token::Symbol(s), _)) if *s == symbol)
+    }
+
+    fn next(&mut self) -> Result<(Token, usize)> {
+        let Some(token) = self.tokens.get(self.pos).cloned() else {
+            bail!("Unexpected end of expression `{}`", self.source);
+        };
+        self.pos += 1;
+        Ok(token)
+    }
+
+    /// Run `parse` one nesting level deeper, failing past [`MAX_DEPTH`]
+    fn nested(&mut self, at: usize, parse: impl FnOnce(&mut Self) -> Result<Expr>) -> Result<Expr> {
+        if self.depth == MAX_DEPTH {
+            bail!(
+                "Expression nested more than {MAX_DEPTH} levels deep at position {at} in `{}`",
+                self.source
+            );
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        match self.next()? {
+            (Token::Symbol(s), _) if s == symbol => Ok(()),
+            (token, at) => bail!(
+                "Expected `{symbol}` but found {token} at position {at} in `{}`",
+                self.source
+            ),
+        }
+    }
+
+    /// Parse operators of one precedence level, left-associative
+    fn binary(
+        &mut self,
+        ops: &[(&str, BinaryOp)],
+        operand: fn(&mut Self) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let mut lhs = operand(self)?;
+        loop {
+            let Some(&(_, op)) = ops.iter().find(|(symbol, _)| self.peek_symbol(symbol)) else {
+                return Ok(lhs);
+            };
+            self.pos += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(operand(self)?));
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        self.binary(&[("||", BinaryOp::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        self.binary(&[("&&", BinaryOp::And)], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let lhs = self.expr()?;
+        let ops = [
+            ("<=", BinaryOp::Le),
+            (">=", BinaryOp::Ge),
+            ("<", BinaryOp::Lt),
+            (">", BinaryOp::Gt),
+            ("==", BinaryOp::Eq),
+            ("!=", BinaryOp::Ne),
+        ];
+        let Some(&(_, op)) = ops.iter().find(|(symbol, _)| self.peek_symbol(symbol)) else {
+            return Ok(lhs);
+        };
+        self.pos += 1;
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(self.expr()?)))
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        self.binary(&[("+", BinaryOp::Add), ("-", BinaryOp::Sub)], Self::term)
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        self.binary(&[("*", BinaryOp::Mul), ("/", BinaryOp::Div)], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        let negate = self.peek_symbol("-");
+        if !negate && !self.peek_symbol("!") {
+            return self.primary();
+        }
+        let at = self.tokens[self.pos].1;
+        self.pos += 1;
+        self.nested(at, |parser| {
+            let inner = Box::new(parser.unary()?);
+            Ok(if negate {
+                Expr::Neg(inner)
+            } else {
+                Expr::Not(inner)
+            })
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next()? {
+            (Token::Number(value), _) => Ok(Expr::Number(value)),
+            (Token::Symbol("("), at) => self.nested(at, |parser| {
+                let inner = parser.or()?;
+                parser.expect_symbol(")")?;
+                Ok(inner)
+            }),
+            (Token::Ident(name), at) if self.peek_symbol("(") => {
+                self.nested(at, |parser| parser.call(&name, at))
+            }
+            (Token::Ident(name), at) => match Metric::from_name(&name) {
+                Some(metric) => Ok(Expr::Metric(metric)),
+                None => bail!(
+                    "Unknown metric `{name}` at position {at} in `{}`",
+                    self.source
+                ),
+            },
+            (token, at) => bail!("Unexpected {token} at position {at} in `{}`", self.source),
+        }
+    }
+
+    fn call(&mut self, name: &str, at: usize) -> Result<Expr> {
+        self.expect_symbol("(")?;
+        if name == "sensor" {
+            let sensor_id = match self.next()? {
+                (Token::Str(sensor_id), _) => sensor_id,
+                (token, at) => bail!(
+                    "Expected a quoted sensor ID but found {token} at position {at} in `{}`",
+                    self.source
+                ),
+            };
+            self.expect_symbol(")")?;
+            return Ok(Expr::Sensor(sensor_id));
+        }
+
+        let function = match name {
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "abs" => Function::Abs,
+            _ => bail!(
+                "Unknown function `{name}` at position {at} in `{}`",
+                self.source
+            ),
+        };
+        let mut args = vec![self.or()?];
+        while self.peek_symbol(",") {
+            self.pos += 1;
+            args.push(self.or()?);
+        }
+        self.expect_symbol(")")?;
+
+        if function == Function::Abs && args.len() != 1 {
+            bail!(
+                "Wrong number of arguments to `{name}` at position {at} in `{}`",
+                self.source
+            );
+        }
+        Ok(Expr::Call(function, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn readings() -> SensorReadings {
+        SensorReadings {
+            cpu_temp: 72.0,
+            cpu_power: 88.0,
+            cpu_usage: 40.0,
+            cpu_freq: 4500.0,
+            cpu_cooler_rpm: 1200.0,
+            gpu_temp: 65.0,
+            gpu_power: 240.0,
+            gpu_usage: 99.0,
+            gpu_freq: 2100.0,
+            ..SensorReadings::default()
+        }
+    }
+
+    fn eval(source: &str) -> f64 {
+        let sensors = HashMap::from([("/lpc/nct6798d/0/temperature/1".to_string(), 31.5)]);
+        Expr::parse(source).unwrap().eval(&readings(), &sensors)
+    }
+
+    #[test]
+    fn test_arithmetic_and_precedence() {
+        assert_eq!(eval("cpu_power + gpu_power"), 328.0);
+        assert_eq!(eval("cpu_power / 110 * 100"), 80.0);
+        assert_eq!(eval("2 + 3 * 4"), 14.0);
+        assert_eq!(eval("(2 + 3) * 4"), 20.0);
+        assert_eq!(eval("-cpu_temp + 100"), 28.0);
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+    }
+
+    #[test]
+    fn test_sensors_and_functions() {
+        assert_eq!(
+            eval("cpu_temp - sensor(\"/lpc/nct6798d/0/temperature/1\")"),
+            40.5
+        );
+        assert_eq!(eval("max(cpu_temp, gpu_temp)"), 72.0);
+        assert_eq!(eval("min(cpu_temp, gpu_temp, 50)"), 50.0);
+        assert_eq!(eval("abs(gpu_temp - cpu_temp)"), 7.0);
+        assert_eq!(
+            Expr::parse("sensor('/a/0/power/0') + sensor('/b/0/power/0')")
+                .unwrap()
+                .sensor_ids(),
+            ["/a/0/power/0", "/b/0/power/0"]
+        );
+    }
+
+    #[test]
+    fn test_conditions() {
+        assert_eq!(eval("gpu_usage > 50"), 1.0);
+        assert_eq!(eval("cpu_temp >= 85"), 0.0);
+        assert_eq!(eval("gpu_usage > 50 && gpu_temp < 60"), 0.0);
+        assert_eq!(
+            eval("gpu_usage > 50 && gpu_temp < 60 || cpu_usage == 40"),
+            1.0
+        );
+        assert_eq!(eval("!(cpu_temp > 80) && cpu_power != 0"), 1.0);
+        assert_eq!(eval("cpu_power + gpu_power > 300"), 1.0);
+        // Comparisons with a blank value are false, whichever way round
+        for source in [
+            "sensor('/missing/0/load/0') > 1",
+            "sensor('/missing/0/load/0') <= 1",
+            "sensor('/missing/0/load/0') != 1",
+        ] {
+            assert_eq!(eval(source), 0.0, "{source}");
+        }
+        assert!(
+            !Expr::parse("sensor('/missing/0/load/0')")
+                .unwrap()
+                .is_true(&readings(), &HashMap::new())
+        );
+    }
+
+    #[test]
+    fn test_missing_values_are_blank() {
+        assert!(eval("cpu_power / 0").is_nan());
+        assert!(eval("sensor('/missing/0/temperature/0') + 1").is_nan());
+        assert!(eval("max(sensor('/missing/0/temperature/0'), 1)").is_nan());
+    }
+
+    #[test]
+    fn test_invalid_expressions_rejected() {
+        for source in [
+            "",
+            "cpu_power +",
+            "cpu_powr + 1",
+            "(cpu_temp",
+            "cpu_temp)",
+            "exec(\"rm\")",
+            "abs(1, 2)",
+            "sensor(cpu_temp)",
+            "1.2.3",
+            "cpu_temp; 1",
+            "cpu_temp > ",
+            "cpu_temp > 1 > 0",
+            "cpu_temp & 1",
+            "cpu_temp = 1",
+        ] {
+            assert!(Expr::parse(source).is_err(), "{source} should be rejected");
+        }
+        // Deep nesting is an error rather than a stack overflow
+        for source in [
+            "-".repeat(100_000) + "1",
+            "(".repeat(100_000) + "1" + &")".repeat(100_000),
+            "abs(".repeat(100) + "1" + &")".repeat(100),
+            "1".to_string() + &" + 1".repeat(100_000),
+        ] {
+            assert!(Expr::parse(&source).is_err());
+        }
+        let nested = "(".repeat(MAX_DEPTH) + "-1" + &")".repeat(MAX_DEPTH);
+        let err = Expr::parse(&nested).unwrap_err();
+        assert!(
+            err.to_string().contains("nested more than 32 levels"),
+            "{err}"
+        );
+        let nested = "(".repeat(MAX_DEPTH - 1) + "-1" + &")".repeat(MAX_DEPTH - 1);
+        assert_eq!(
+            Expr::parse(&nested)
+                .unwrap()
+                .eval(&readings(), &HashMap::new()),
+            -1.0
+        );
+
+        let err = Expr::parse("cpu_powr + 1").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Unknown metric `cpu_powr` at position 0")
+        );
+    }
+}
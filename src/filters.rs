@@ -0,0 +1,222 @@
+use crate::config::FilterConfig;
+use crate::sensor_readings::{Metric, SensorReadings};
+use std::collections::{HashMap, VecDeque};
+use tracing::info;
+
+/// Per-metric smoothing chains run on each reading before it is displayed
+pub struct Filters {
+    chains: HashMap<Metric, Vec<Filter>>,
+    config: HashMap<Metric, Vec<FilterConfig>>,
+}
+
+impl Filters {
+    pub fn new(config: &HashMap<Metric, Vec<FilterConfig>>) -> Self {
+        let mut chains = HashMap::new();
+        for metric in Metric::ALL {
+            let Some(stages) = config.get(&metric).filter(|stages| !stages.is_empty()) else {
+                continue;
+            };
+            let chain = stages.iter().map(|stage| Filter::new(*stage)).collect();
+            info!(metric = metric.name(), filters = ?stages, "Smoothing filters");
+            chains.insert(metric, chain);
+        }
+        Self {
+            chains,
+            config: config.clone(),
+        }
+    }
+
+    /// Keep the state of every chain configured the same as in `previous`
+    pub fn inherit(&mut self, previous: &Filters) {
+        for (metric, chain) in &mut self.chains {
+            if let Some(old) = previous.chains.get(metric)
+                && previous.config.get(metric) == self.config.get(metric)
+            {
+                chain.clone_from(old);
+            }
+        }
+    }
+
+    /// Feed one reading through every metric's chain
+    ///
+    /// Blank (NaN) values pass through without touching the filter state.
+    pub fn apply(&mut self, readings: &SensorReadings) -> SensorReadings {
+        let mut filtered = *readings;
+        for (metric, chain) in &mut self.chains {
+            let value = readings.get(*metric);
+            if value.is_nan() {
+                continue;
+            }
+            let value = chain
+                .iter_mut()
+                .fold(value, |value, filter| filter.apply(value));
+            filtered.set(*metric, value);
+        }
+        filtered
+    }
+}
+
+#[derive(Clone)]
+enum Filter {
+    Ema {
+        alpha: f64,
+        average: Option<f64>,
+    },
+    Median {
+        window: usize,
+        samples: VecDeque<f64>,
+    },
+    Deadband {
+        width: f64,
+        shown: Option<f64>,
+    },
+}
+
+impl Filter {
+    /// Parameters are checked when the config is parsed
+    fn new(config: FilterConfig) -> Self {
+        match config {
+            FilterConfig::Ema { alpha } => Filter::Ema {
+                alpha,
+                average: None,
+            },
+            FilterConfig::Median { window } => Filter::Median {
+                window,
+                samples: VecDeque::with_capacity(window),
+            },
+            FilterConfig::Deadband { width } => Filter::Deadband { width, shown: None },
+        }
+    }
+
+    fn apply(&mut self, value: f64) -> f64 {
+        match self {
+            Filter::Ema { alpha, average } => {
+                let next = match average {
+                    Some(average) => *alpha * value + (1.0 - *alpha) * *average,
+                    None => value,
+                };
+                *average = Some(next);
+                next
+            }
+            Filter::Median { window, samples } => {
+                if samples.len() == *window {
+                    samples.pop_front();
+                }
+                samples.push_back(value);
+
+                let mut sorted: Vec<f64> = samples.iter().copied().collect();
+                sorted.sort_by(f64::total_cmp);
+                let mid = sorted.len() / 2;
+                if sorted.len().is_multiple_of(2) {
+                    (sorted[mid - 1] + sorted[mid]) / 2.0
+                } else {
+                    sorted[mid]
+                }
+            }
+            Filter::Deadband { width, shown } => match shown {
+                Some(shown) if (value - *shown).abs() <= *width => *shown,
+                _ => {
+                    *shown = Some(value);
+                    value
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    // CPU package temperature polled once a second while a game loads,
+    // with the usual single-sample spikes from the sensor
+    const CPU_TEMP: [f64; 12] = [
+        45.0, 45.3, 44.9, 61.0, 45.2, 45.6, 52.8, 53.4, 53.1, 54.0, 53.6, 53.8,
+    ];
+
+    fn run(config: FilterConfig, input: &[f64]) -> Vec<f64> {
+        let mut filter = Filter::new(config);
+        input.iter().map(|&value| filter.apply(value)).collect()
+    }
+
+    fn rounded(values: Vec<f64>) -> Vec<f64> {
+        values
+            .into_iter()
+            .map(|value| (value * 100.0).round() / 100.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_ema() {
+        let output = rounded(run(FilterConfig::Ema { alpha: 0.5 }, &CPU_TEMP[..5]));
+        assert_eq!(output, [45.0, 45.15, 45.03, 53.01, 49.11]);
+    }
+
+    #[test]
+    fn test_median_rejects_single_spikes() {
+        let output = run(FilterConfig::Median { window: 3 }, &CPU_TEMP);
+        assert_eq!(
+            output,
+            [
+                45.0, 45.15, 45.0, 45.3, 45.2, 45.6, 45.6, 52.8, 53.1, 53.4, 53.6, 53.8
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deadband_holds_small_changes() {
+        let output = run(FilterConfig::Deadband { width: 0.5 }, &CPU_TEMP);
+        assert_eq!(
+            output,
+            [
+                45.0, 45.0, 45.0, 61.0, 45.2, 45.2, 52.8, 53.4, 53.4, 54.0, 54.0, 54.0
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chain_and_blank_values() {
+        let config = HashMap::from([(
+            Metric::CpuTemp,
+            vec![
+                FilterConfig::Median { window: 3 },
+                FilterConfig::Deadband { width: 0.5 },
+            ],
+        )]);
+        let mut filters = Filters::new(&config);
+        let mut readings = SensorReadings::default();
+
+        let mut output = Vec::new();
+        for temp in CPU_TEMP {
+            readings.cpu_temp = temp;
+            readings.gpu_power = temp * 4.0;
+            let filtered = filters.apply(&readings);
+            // Metrics without filters pass through
+            assert_eq!(filtered.gpu_power, temp * 4.0);
+            output.push(filtered.cpu_temp);
+        }
+        assert_eq!(
+            output,
+            [
+                45.0, 45.0, 45.0, 45.0, 45.0, 45.6, 45.6, 52.8, 52.8, 53.4, 53.4, 53.4
+            ]
+        );
+
+        readings.cpu_temp = f64::NAN;
+        assert!(filters.apply(&readings).cpu_temp.is_nan());
+    }
+
+    #[test]
+    fn test_invalid_parameters_rejected() {
+        for filter in [
+            "{ type = \"ema\", alpha = 0.0 }",
+            "{ type = \"ema\", alpha = 1.5 }",
+            "{ type = \"median\", window = 0 }",
+            "{ type = \"deadband\", width = -1.0 }",
+        ] {
+            let toml = format!("[filters]\ncpu_temp = [{filter}]");
+            assert!(Config::from_toml(&toml).is_err(), "{filter}");
+        }
+    }
+}
@@ -1,5 +1,6 @@
+use crate::shutdown::Shutdown;
 use anyhow::{Context, Result};
-use std::{thread::sleep, time::Duration};
+use std::time::Duration;
 use tracing::error;
 
 /// Retry an operation with exponential backoff
@@ -7,6 +8,7 @@ use tracing::error;
 /// # Arguments
 /// * `max_retries` - Maximum number of retry attempts
 /// * `delay_secs` - Delay in seconds between retries
+/// * `shutdown` - Cuts the delay short and stops retrying when triggered
 /// * `f` - The operation to retry
 ///
 /// # Returns
@@ -14,13 +16,19 @@ use tracing::error;
 /// * `Err(anyhow::Error)` - The error from the last failed attempt
 ///
 /// # Example
-/// ```
-/// let result = retry_with_backoff(3, 5, || {
+/// ```ignore
+/// let shutdown = Shutdown::new();
+/// let result = retry_with_backoff(3, 5, &shutdown, || {
 ///     // Your operation here
 ///     Ok(42)
 /// })?;
 /// ```
-pub fn retry_with_backoff<F, T>(max_retries: u32, delay_secs: u64, mut f: F) -> Result<T>
+pub fn retry_with_backoff<F, T>(
+    max_retries: u32,
+    delay_secs: u64,
+    shutdown: &Shutdown,
+    mut f: F,
+) -> Result<T>
 where
     F: FnMut() -> Result<T>,
 {
@@ -45,7 +53,9 @@ where
                     delay_secs
                 );
 
-                sleep(Duration::from_secs(delay_secs));
+                if shutdown.sleep(Duration::from_secs(delay_secs)) {
+                    return Err(err).context("Shutdown requested while retrying");
+                }
             }
         }
     }
@@ -59,7 +69,7 @@ mod tests {
 
     #[test]
     fn test_retry_success_on_first_attempt() {
-        let result = retry_with_backoff(3, 1, || Ok(42));
+        let result = retry_with_backoff(3, 1, &Shutdown::new(), || Ok(42));
         assert_eq!(result.unwrap(), 42);
     }
 
@@ -68,7 +78,7 @@ mod tests {
         let counter = Arc::new(AtomicU32::new(0));
         let counter_clone = counter.clone();
 
-        let result = retry_with_backoff(3, 0, move || {
+        let result = retry_with_backoff(3, 0, &Shutdown::new(), move || {
             let count = counter_clone.fetch_add(1, Ordering::SeqCst);
             if count < 2 {
                 anyhow::bail!("Temporary failure")
@@ -86,7 +96,7 @@ mod tests {
         let counter = Arc::new(AtomicU32::new(0));
         let counter_clone = counter.clone();
 
-        let result: Result<i32> = retry_with_backoff(3, 0, move || {
+        let result: Result<i32> = retry_with_backoff(3, 0, &Shutdown::new(), move || {
             counter_clone.fetch_add(1, Ordering::SeqCst);
             anyhow::bail!("Always fails")
         });
@@ -94,4 +104,22 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(counter.load(Ordering::SeqCst), 3); // Tried exactly 3 times
     }
+
+    #[test]
+    fn test_retry_stops_on_shutdown() {
+        let counter = Arc::new(AtomicU32::new(0));
+        let counter_clone = counter.clone();
+        let shutdown = Shutdown::new();
+        shutdown.trigger();
+
+        let start = std::time::Instant::now();
+        let result: Result<i32> = retry_with_backoff(3, 5, &shutdown, move || {
+            counter_clone.fetch_add(1, Ordering::SeqCst);
+            anyhow::bail!("Always fails")
+        });
+
+        assert!(result.is_err());
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
 }
@@ -0,0 +1,575 @@
+use crate::sensor_readings::TemperatureUnit;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::debug;
+
+/// Sensor kinds as they appear in LHM `SensorId`s and legacy group icon names
+const SENSOR_KINDS: &[&str] = &[
+    "voltage",
+    "current",
+    "power",
+    "clock",
+    "temperature",
+    "load",
+    "frequency",
+    "fan",
+    "flow",
+    "control",
+    "level",
+    "factor",
+    "data",
+    "smalldata",
+    "throughput",
+    "timespan",
+    "energy",
+    "noise",
+    "conductivity",
+    "humidity",
+];
+
+#[derive(Debug, Deserialize)]
+pub struct LHMData {
+    #[serde(rename = "Children")]
+    pub children: Vec<LHMDataChildren>,
+}
+
+/// Layout of a data.json document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schema {
+    /// Pre-release LibreHardwareMonitor, with `HardwareId` and `SensorId` fields
+    Identified,
+    /// Stable LibreHardwareMonitor and OpenHardwareMonitor, with only `Text` and `ImageURL`
+    Legacy,
+}
+
+impl LHMData {
+    /// Detect the document schema and, for legacy documents, synthesize
+    /// `HardwareId`s and `SensorId`s from hardware/group names and icons
+    ///
+    /// Synthesized sensor IDs number sensors by their position within a group,
+    /// so they follow the `/<hardware>/<index>/<kind>/<index>` shape but won't
+    /// always equal the IDs a pre-release LHM would report for the same sensor.
+    pub fn normalize(&mut self) -> Schema {
+        if self
+            .children
+            .iter()
+            .any(|computer| !computer.sensors().is_empty())
+        {
+            return Schema::Identified;
+        }
+
+        let mut hardware_counts = HashMap::new();
+        for computer in &mut self.children {
+            computer.assign_legacy_ids(None, &mut hardware_counts);
+        }
+        Schema::Legacy
+    }
+}
+
+/// A parsed sensor value, normalized to one canonical unit per quantity
+#[derive(Debug, Default)]
+pub enum LHMDataValue {
+    Temperature(f64, TemperatureUnit),
+    /// Watts
+    Power(f64),
+    /// Load, control duty, level or humidity in percent
+    Percentage(f64),
+    /// Megahertz
+    Frequency(f64),
+    Rpm(f64),
+    /// Volts
+    Voltage(f64),
+    /// Amperes
+    Current(f64),
+    /// Megabytes
+    Data(f64),
+    /// Kilobytes per second
+    Throughput(f64),
+    /// Watt-hours
+    Energy(f64),
+    /// Decibels
+    Noise(f64),
+    /// Litres per hour
+    Flow(f64),
+    /// Unitless ratio
+    Factor(f64),
+    #[default]
+    Unknown,
+}
+
+impl LHMDataValue {
+    pub fn as_temperature(&self) -> Option<(f64, TemperatureUnit)> {
+        if let LHMDataValue::Temperature(val, unit) = self {
+            Some((*val, *unit))
+        } else {
+            None
+        }
+    }
+
+    /// The value in its canonical unit, whatever the quantity
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            LHMDataValue::Temperature(val, _)
+            | LHMDataValue::Power(val)
+            | LHMDataValue::Percentage(val)
+            | LHMDataValue::Frequency(val)
+            | LHMDataValue::Rpm(val)
+            | LHMDataValue::Voltage(val)
+            | LHMDataValue::Current(val)
+            | LHMDataValue::Data(val)
+            | LHMDataValue::Throughput(val)
+            | LHMDataValue::Energy(val)
+            | LHMDataValue::Noise(val)
+            | LHMDataValue::Flow(val)
+            | LHMDataValue::Factor(val) => Some(*val),
+            LHMDataValue::Unknown => None,
+        }
+    }
+
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            LHMDataValue::Temperature(..) => "Temperature",
+            LHMDataValue::Power(_) => "Power",
+            LHMDataValue::Percentage(_) => "Percentage",
+            LHMDataValue::Frequency(_) => "Frequency",
+            LHMDataValue::Rpm(_) => "Rpm",
+            LHMDataValue::Voltage(_) => "Voltage",
+            LHMDataValue::Current(_) => "Current",
+            LHMDataValue::Data(_) => "Data",
+            LHMDataValue::Throughput(_) => "Throughput",
+            LHMDataValue::Energy(_) => "Energy",
+            LHMDataValue::Noise(_) => "Noise",
+            LHMDataValue::Flow(_) => "Flow",
+            LHMDataValue::Factor(_) => "Factor",
+            LHMDataValue::Unknown => "Unknown",
+        }
+    }
+
+    /// Build a value from a number and its LHM unit suffix
+    fn from_unit(val: f64, unit: &str) -> Self {
+        const KIB: f64 = 1024.0;
+        match unit {
+            "°C" => LHMDataValue::Temperature(val, TemperatureUnit::Celsius),
+            "°F" => LHMDataValue::Temperature(val, TemperatureUnit::Fahrenheit),
+            "mW" => LHMDataValue::Power(val / 1000.0),
+            "W" => LHMDataValue::Power(val),
+            "kW" => LHMDataValue::Power(val * 1000.0),
+            "%" => LHMDataValue::Percentage(val),
+            "Hz" => LHMDataValue::Frequency(val / 1_000_000.0),
+            "kHz" => LHMDataValue::Frequency(val / 1000.0),
+            "MHz" => LHMDataValue::Frequency(val),
+            "GHz" => LHMDataValue::Frequency(val * 1000.0),
+            "RPM" => LHMDataValue::Rpm(val),
+            "mV" => LHMDataValue::Voltage(val / 1000.0),
+            "V" => LHMDataValue::Voltage(val),
+            "mA" => LHMDataValue::Current(val / 1000.0),
+            "A" => LHMDataValue::Current(val),
+            "KB" => LHMDataValue::Data(val / KIB),
+            "MB" => LHMDataValue::Data(val),
+            "GB" => LHMDataValue::Data(val * KIB),
+            "TB" => LHMDataValue::Data(val * KIB * KIB),
+            "B/s" => LHMDataValue::Throughput(val / KIB),
+            "KB/s" => LHMDataValue::Throughput(val),
+            "MB/s" => LHMDataValue::Throughput(val * KIB),
+            "GB/s" => LHMDataValue::Throughput(val * KIB * KIB),
+            "mWh" => LHMDataValue::Energy(val / 1000.0),
+            "Wh" => LHMDataValue::Energy(val),
+            "kWh" => LHMDataValue::Energy(val * 1000.0),
+            "dB" | "dBA" => LHMDataValue::Noise(val),
+            "L/h" => LHMDataValue::Flow(val),
+            "x" => LHMDataValue::Factor(val),
+            _ => LHMDataValue::Unknown,
+        }
+    }
+}
+
+impl std::fmt::Display for LHMDataValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LHMDataValue::Temperature(val, unit) => write!(f, "{:.1} °{}", val, unit.to_str()),
+            LHMDataValue::Power(val) => write!(f, "{:.1} W", val),
+            LHMDataValue::Percentage(val) => write!(f, "{:.1} %", val),
+            LHMDataValue::Frequency(val) => write!(f, "{:.1} MHz", val),
+            LHMDataValue::Rpm(val) => write!(f, "{:.0} RPM", val),
+            LHMDataValue::Voltage(val) => write!(f, "{:.3} V", val),
+            LHMDataValue::Current(val) => write!(f, "{:.3} A", val),
+            LHMDataValue::Data(val) => write!(f, "{:.1} MB", val),
+            LHMDataValue::Throughput(val) => write!(f, "{:.1} KB/s", val),
+            LHMDataValue::Energy(val) => write!(f, "{:.1} Wh", val),
+            LHMDataValue::Noise(val) => write!(f, "{:.1} dB", val),
+            LHMDataValue::Flow(val) => write!(f, "{:.1} L/h", val),
+            LHMDataValue::Factor(val) => write!(f, "{:.3}", val),
+            LHMDataValue::Unknown => write!(f, "-"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LHMDataValue {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct LHMDataValueVisitor;
+        impl<'de> serde::de::Visitor<'de> for LHMDataValueVisitor {
+            type Value = LHMDataValue;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a string representing a sensor value")
+            }
+
+            fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                // Unparseable values degrade to Unknown so a single odd sensor
+                // doesn't discard the whole poll
+                let Some((val, unit)) = v.trim().rsplit_once(char::is_whitespace) else {
+                    // Factors are printed without a unit
+                    return Ok(
+                        parse_number(v, None).map_or(LHMDataValue::Unknown, LHMDataValue::Factor)
+                    );
+                };
+                let Some(val) = parse_number(val, Some(unit)) else {
+                    debug!(value = v, "Failed to parse LHM sensor value");
+                    return Ok(LHMDataValue::Unknown);
+                };
+                Ok(LHMDataValue::from_unit(val, unit))
+            }
+        }
+        deserializer.deserialize_str(LHMDataValueVisitor)
+    }
+}
+
+/// Units LHM prints without decimals
+const INTEGER_UNITS: &[&str] = &["RPM"];
+/// Units LHM prints with three decimals, so `1.204` in them is a fraction
+const THREE_DECIMAL_UNITS: &[&str] = &["V", "A", "x"];
+
+/// Parse a number formatted with either `.` or `,` as the decimal separator
+///
+/// LHM formats values with the machine's regional settings, so `45,3`,
+/// `1.204,5`, `1,204.5` and `1 204` all occur. When both separators appear the
+/// last one is the decimal separator; a separator that repeats is a thousands
+/// separator. A single separator is a thousands separator in a unit printed
+/// without decimals, or when exactly three digits follow it in a unit printed
+/// with fewer than three, and a decimal separator otherwise. `unit` is `None`
+/// for factors, which have three decimals.
+fn parse_number(text: &str, unit: Option<&str>) -> Option<f64> {
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let last_dot = digits.rfind('.');
+    let last_comma = digits.rfind(',');
+
+    let groups_thousands = |separator: usize| {
+        let fraction = &digits[separator + 1..];
+        unit.is_some_and(|unit| {
+            INTEGER_UNITS.contains(&unit)
+                || (!THREE_DECIMAL_UNITS.contains(&unit)
+                    && fraction.len() == 3
+                    && fraction.chars().all(|c| c.is_ascii_digit()))
+        })
+    };
+    let decimal_separator = match (last_dot, last_comma) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(dot), None) if digits.matches('.').count() == 1 && !groups_thousands(dot) => {
+            Some('.')
+        }
+        (None, Some(comma)) if digits.matches(',').count() == 1 && !groups_thousands(comma) => {
+            Some(',')
+        }
+        _ => None,
+    };
+
+    let normalized: String = digits
+        .chars()
+        .filter_map(|c| match c {
+            '.' | ',' if Some(c) == decimal_separator => Some('.'),
+            '.' | ',' => None,
+            c => Some(c),
+        })
+        .collect();
+    normalized.parse::<f64>().ok().filter(|val| val.is_finite())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LHMDataChildren {
+    #[serde(rename = "Text", default)]
+    pub text: String,
+    #[serde(rename = "Value")]
+    pub value: LHMDataValue,
+    /// Lowest value since LHM started or its Min/Max were last reset
+    #[serde(rename = "Min", default)]
+    pub min: LHMDataValue,
+    /// Highest value since LHM started or its Min/Max were last reset
+    #[serde(rename = "Max", default)]
+    pub max: LHMDataValue,
+    #[serde(rename = "ImageURL", default)]
+    pub image_url: String,
+    #[serde(rename = "HardwareId")]
+    pub hardware_id: Option<String>,
+    #[serde(rename = "SensorId")]
+    pub sensor_id: Option<String>,
+    #[serde(rename = "Children")]
+    pub children: Vec<LHMDataChildren>,
+}
+
+impl LHMDataChildren {
+    /// All sensor nodes in this subtree, in document order
+    pub fn sensors(&self) -> Vec<&LHMDataChildren> {
+        let mut sensors = Vec::new();
+        self.collect_sensors(&mut sensors);
+        sensors
+    }
+
+    fn collect_sensors<'a>(&'a self, sensors: &mut Vec<&'a LHMDataChildren>) {
+        if self.sensor_id.is_some() {
+            sensors.push(self);
+        }
+        for child in &self.children {
+            child.collect_sensors(sensors);
+        }
+    }
+
+    /// All hardware nodes in this subtree, including nested ones such as Super I/O chips
+    pub fn hardware(&self) -> Vec<&LHMDataChildren> {
+        let mut hardware = Vec::new();
+        self.collect_hardware(&mut hardware);
+        hardware
+    }
+
+    fn collect_hardware<'a>(&'a self, hardware: &mut Vec<&'a LHMDataChildren>) {
+        if self.hardware_id.is_some() {
+            hardware.push(self);
+        }
+        for child in &self.children {
+            child.collect_hardware(hardware);
+        }
+    }
+
+    /// Sensor kind segment of the `SensorId`, e.g. `temperature` for `/amdcpu/0/temperature/2`
+    pub fn sensor_kind(&self) -> Option<&str> {
+        self.sensor_id.as_deref()?.rsplit('/').nth(1)
+    }
+
+    /// Hardware type segment of the `HardwareId` or `SensorId`, e.g. `amdcpu`
+    /// for `/amdcpu/0` or `/amdcpu/0/temperature/2`
+    pub fn hardware_type(&self) -> Option<&str> {
+        self.hardware_id
+            .as_deref()
+            .or(self.sensor_id.as_deref())?
+            .split('/')
+            .nth(1)
+    }
+
+    fn assign_legacy_ids(
+        &mut self,
+        hardware_id: Option<&str>,
+        hardware_counts: &mut HashMap<String, usize>,
+    ) {
+        if let Some(kind) = self.legacy_group_kind() {
+            if let Some(hardware_id) = hardware_id {
+                for (index, sensor) in self.children.iter_mut().enumerate() {
+                    sensor.sensor_id = Some(format!("{hardware_id}/{kind}/{index}"));
+                }
+            }
+            return;
+        }
+
+        let hardware_id = match self.legacy_hardware_type() {
+            Some(hardware_type) => {
+                let id = if hardware_type == "motherboard" {
+                    String::from("/motherboard")
+                } else {
+                    let index = hardware_counts.entry(hardware_type.clone()).or_insert(0);
+                    *index += 1;
+                    format!("/{hardware_type}/{}", *index - 1)
+                };
+                self.hardware_id = Some(id.clone());
+                Some(id)
+            }
+            None => hardware_id.map(str::to_string),
+        };
+
+        for child in &mut self.children {
+            child.assign_legacy_ids(hardware_id.as_deref(), hardware_counts);
+        }
+    }
+
+    /// Icon file stem, e.g. `cpu` for `images_icon/cpu.png`
+    fn icon_name(&self) -> Option<&str> {
+        let file = self.image_url.rsplit('/').next()?;
+        let (stem, _) = file.rsplit_once('.')?;
+        self.image_url.contains("images_icon/").then_some(stem)
+    }
+
+    /// Sensor kind of a legacy group node such as `Temperatures`, from its icon or name
+    fn legacy_group_kind(&self) -> Option<&'static str> {
+        if self.children.is_empty() {
+            return None;
+        }
+        let name = match self.icon_name() {
+            Some(icon) => icon.to_lowercase(),
+            None => self.text.to_lowercase(),
+        };
+        let singular = name.strip_suffix('s').unwrap_or(&name);
+        SENSOR_KINDS
+            .iter()
+            .find(|kind| **kind == name || **kind == singular)
+            .copied()
+    }
+
+    /// LHM hardware type of a legacy hardware node, e.g. `amdcpu` or `lpc/nct6798d`
+    fn legacy_hardware_type(&self) -> Option<String> {
+        let text = self.text.to_lowercase();
+        let hardware_type = match self.icon_name()? {
+            "computer" => return None,
+            "cpu" if text.contains("amd") => "amdcpu",
+            "cpu" if text.contains("intel") => "intelcpu",
+            "cpu" => "cpu",
+            "nvidia" => "gpu-nvidia",
+            "ati" | "amd" => "gpu-amd",
+            "intel" => "gpu-intel",
+            "mainboard" => "motherboard",
+            "chip" => {
+                let chip = text.split_whitespace().last().unwrap_or("superio");
+                return Some(format!("lpc/{chip}"));
+            }
+            icon => return Some(icon.to_lowercase()),
+        };
+        Some(hardware_type.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_value(text: &str) -> LHMDataValue {
+        serde_json::from_value(serde_json::Value::String(text.to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_parse_number_separators() {
+        assert_eq!(parse_number("45.3", None), Some(45.3));
+        assert_eq!(parse_number("45,3", None), Some(45.3));
+        assert_eq!(parse_number("1.204,5", None), Some(1204.5));
+        assert_eq!(parse_number("1,204.5", None), Some(1204.5));
+        assert_eq!(parse_number("1.234.567", None), Some(1234567.0));
+        assert_eq!(parse_number("1 204", None), Some(1204.0));
+        assert_eq!(parse_number("1\u{202f}204,5", None), Some(1204.5));
+        assert_eq!(parse_number("-0,5", None), Some(-0.5));
+        assert_eq!(parse_number("n/a", None), None);
+        assert_eq!(parse_number("", None), None);
+        // A lone separator depends on how many decimals the unit is printed with
+        assert_eq!(parse_number("1.204", Some("RPM")), Some(1204.0));
+        assert_eq!(parse_number("1,2", Some("RPM")), Some(12.0));
+        assert_eq!(parse_number("4.688", Some("MHz")), Some(4688.0));
+        assert_eq!(parse_number("4.68", Some("MHz")), Some(4.68));
+        assert_eq!(parse_number("1,204", Some("V")), Some(1.204));
+        assert_eq!(parse_number("1.204", None), Some(1.204));
+    }
+
+    #[test]
+    fn test_parse_localized_values() {
+        assert!(matches!(
+            parse_value("45,3 °C"),
+            LHMDataValue::Temperature(val, TemperatureUnit::Celsius) if val == 45.3
+        ));
+        assert!(matches!(parse_value("1.204 RPM"), LHMDataValue::Rpm(val) if val == 1204.0));
+        assert!(matches!(parse_value("1\u{a0}204 RPM"), LHMDataValue::Rpm(val) if val == 1204.0));
+        assert!(
+            matches!(parse_value("4.688,2 MHz"), LHMDataValue::Frequency(val) if val == 4688.2)
+        );
+    }
+
+    #[test]
+    fn test_units_are_normalized() {
+        let cases = [
+            ("1.104 V", "Voltage", 1.104),
+            ("850 mV", "Voltage", 0.85),
+            ("12,5 A", "Current", 12.5),
+            ("750 mA", "Current", 0.75),
+            ("1.2 kW", "Power", 1200.0),
+            ("500 mW", "Power", 0.5),
+            ("4.8 GHz", "Frequency", 4800.0),
+            ("2400 kHz", "Frequency", 2.4),
+            ("12.5 GB", "Data", 12800.0),
+            ("6144.0 MB", "Data", 6144.0),
+            ("512.0 KB/s", "Throughput", 512.0),
+            ("2.5 MB/s", "Throughput", 2560.0),
+            ("42 %", "Percentage", 42.0),
+            ("31.5 dBA", "Noise", 31.5),
+            ("120.0 L/h", "Flow", 120.0),
+            ("2500 mWh", "Energy", 2.5),
+            ("1.05", "Factor", 1.05),
+        ];
+        for (text, kind, expected) in cases {
+            let value = parse_value(text);
+            assert_eq!(value.kind_name(), kind, "{text}");
+            let number = value.as_number().unwrap();
+            assert!((number - expected).abs() < 1e-9, "{text}: {number}");
+        }
+    }
+
+    #[test]
+    fn test_unparseable_value_is_unknown() {
+        assert!(matches!(parse_value("abc °C"), LHMDataValue::Unknown));
+        assert!(matches!(parse_value("3 parsecs"), LHMDataValue::Unknown));
+        assert!(matches!(parse_value(""), LHMDataValue::Unknown));
+        assert!(matches!(parse_value("Value"), LHMDataValue::Unknown));
+    }
+
+    #[test]
+    fn test_identified_schema_is_left_alone() {
+        let mut data: LHMData =
+            serde_json::from_str(include_str!("../testdata/lhm-pre-release.json")).unwrap();
+        assert_eq!(data.normalize(), Schema::Identified);
+        assert_eq!(
+            data.children[0].sensors()[0].sensor_id.as_deref(),
+            Some("/lpc/nct6798d/0/voltage/0")
+        );
+    }
+
+    #[test]
+    fn test_legacy_schema_gets_synthesized_ids() {
+        let mut data: LHMData =
+            serde_json::from_str(include_str!("../testdata/ohm-legacy.json")).unwrap();
+        assert_eq!(data.normalize(), Schema::Legacy);
+
+        let computer = &data.children[0];
+        let hardware_ids: Vec<&str> = computer
+            .children
+            .iter()
+            .filter_map(|hardware| hardware.hardware_id.as_deref())
+            .collect();
+        assert_eq!(hardware_ids, ["/motherboard", "/amdcpu/0", "/gpu-amd/0"]);
+        assert_eq!(
+            computer.children[0].children[0].hardware_id.as_deref(),
+            Some("/lpc/it8686e/0")
+        );
+
+        let sensor_ids: Vec<&str> = computer
+            .sensors()
+            .iter()
+            .filter_map(|sensor| sensor.sensor_id.as_deref())
+            .collect();
+        assert!(sensor_ids.contains(&"/lpc/it8686e/0/fan/0"));
+        assert!(sensor_ids.contains(&"/amdcpu/0/temperature/0"));
+        assert!(sensor_ids.contains(&"/amdcpu/0/load/0"));
+        assert!(sensor_ids.contains(&"/gpu-amd/0/clock/0"));
+    }
+
+    #[test]
+    fn test_bad_value_does_not_fail_whole_tree() {
+        let data: LHMData = serde_json::from_str(
+            r#"{"Children": [{"Text": "PC", "Value": "", "Children": [
+                {"Text": "Broken", "Value": "?? °C", "SensorId": "/amdcpu/0/temperature/0", "Children": []},
+                {"Text": "Package", "Value": "61,5 W", "SensorId": "/amdcpu/0/power/0", "Children": []}
+            ]}]}"#,
+        )
+        .unwrap();
+
+        let sensors = data.children[0].sensors();
+        assert!(matches!(sensors[0].value, LHMDataValue::Unknown));
+        assert!(matches!(sensors[1].value, LHMDataValue::Power(val) if val == 61.5));
+    }
+}
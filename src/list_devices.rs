@@ -0,0 +1,31 @@
+use crate::ch_170::list_displays;
+use crate::config::HidConfig;
+use anyhow::Result;
+
+/// Print every connected CH170, marking the one selected by `--device` or
+/// `[hid] device`
+pub fn run(hid: &HidConfig) -> Result<()> {
+    let displays = list_displays(hid)?;
+    if displays.is_empty() {
+        println!(
+            "No CH170 displays found (VID: 0x{:04X}, PID: 0x{:04X})",
+            hid.vendor_id, hid.product_id
+        );
+        return Ok(());
+    }
+
+    for display in &displays {
+        let selected = match &hid.device {
+            Some(selector) => display.matches(selector),
+            None => false,
+        };
+        println!(
+            "{} {:<32} serial {:<16} {}",
+            if selected { "*" } else { " " },
+            display.path,
+            display.serial_number.as_deref().unwrap_or("-"),
+            display.product.as_deref().unwrap_or("")
+        );
+    }
+    Ok(())
+}
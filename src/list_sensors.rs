@@ -0,0 +1,124 @@
+use crate::config::Config;
+use crate::lhm_data::{LHMData, LHMDataChildren, Schema};
+use crate::sensor_discovery::discover_sensor;
+use crate::sensor_reader::SensorReader;
+use crate::sensor_readings::Metric;
+use anyhow::{Context, Result};
+use std::fmt::Write;
+
+/// Print the LHM sensor tree, optionally followed by a `[sensors]` config block
+/// for the automatically detected CPU, GPU and fan sensors
+pub fn run(config: &Config, emit_config: bool) -> Result<()> {
+    let reader = SensorReader::new(config).context("Failed to initialize sensor reader")?;
+    let (data, schema) = reader.fetch()?;
+
+    if schema == Schema::Legacy {
+        println!("# Legacy data.json without sensor IDs, IDs below are synthesized");
+    }
+    print!("{}", format_tree(&data));
+    if emit_config {
+        println!();
+        print!("{}", format_mapping_config(&data));
+    }
+    Ok(())
+}
+
+fn format_tree(data: &LHMData) -> String {
+    let mut output = String::new();
+    for computer in &data.children {
+        format_node(&mut output, computer, 0);
+    }
+    output
+}
+
+fn format_node(output: &mut String, node: &LHMDataChildren, depth: usize) {
+    let indent = "  ".repeat(depth);
+    match (&node.hardware_id, &node.sensor_id) {
+        (_, Some(sensor_id)) => {
+            let _ = writeln!(
+                output,
+                "{indent}{sensor_id:<36} {:<28} {:<12} {} (min {}, max {})",
+                node.text,
+                node.value.kind_name(),
+                node.value,
+                node.min,
+                node.max
+            );
+        }
+        (Some(hardware_id), None) => {
+            let _ = writeln!(output, "{indent}{} [{hardware_id}]", node.text);
+        }
+        (None, None) => {
+            let _ = writeln!(output, "{indent}{}", node.text);
+        }
+    }
+
+    for child in &node.children {
+        format_node(output, child, depth + 1);
+    }
+}
+
+fn format_mapping_config(data: &LHMData) -> String {
+    let mut output = String::from("[sensors]\n");
+    let Some(computer) = data.children.first() else {
+        return output;
+    };
+
+    for metric in Metric::ALL {
+        match discover_sensor(computer, metric) {
+            Some(sensor) => {
+                let _ = writeln!(
+                    output,
+                    "{} = \"{}\" # {}, {}",
+                    metric.name(),
+                    sensor.sensor_id.as_deref().unwrap_or_default(),
+                    sensor.text,
+                    sensor.value
+                );
+            }
+            None => {
+                let _ = writeln!(
+                    output,
+                    "# {} = \"\" # no matching sensor found",
+                    metric.name()
+                );
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> LHMData {
+        serde_json::from_str(include_str!("../testdata/lhm-pre-release.json")).unwrap()
+    }
+
+    #[test]
+    fn test_tree_lists_hardware_and_sensors() {
+        let tree = format_tree(&data());
+        assert!(tree.contains("12th Gen Intel Core i7-12700K [/intelcpu/0]"));
+        assert!(tree.lines().any(|line| {
+            line.contains("/intelcpu/0/temperature/12")
+                && line.contains("CPU Package")
+                && line.contains("Temperature")
+                && line.contains("47.0 °C (min 31.0 °C, max 84.0 °C)")
+        }));
+    }
+
+    #[test]
+    fn test_mapping_config_is_valid_toml() {
+        let snippet = format_mapping_config(&data());
+        let config = Config::from_toml(&snippet).unwrap();
+        assert_eq!(
+            config.sensors.sensor_ids(Metric::CpuTemp),
+            ["/intelcpu/0/temperature/12"]
+        );
+        assert_eq!(
+            config.sensors.sensor_ids(Metric::CpuCoolerRpm),
+            ["/lpc/nct6798d/0/fan/1"]
+        );
+    }
+}
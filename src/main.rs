@@ -2,100 +2,280 @@
 // #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod ch_170;
+mod check_config;
+mod cli;
+mod config;
+mod derived;
+mod expression;
+mod filters;
 mod helpers;
+mod lhm_data;
+mod list_devices;
+mod list_sensors;
+mod mode_rules;
+mod oversampling;
+mod pipeline;
+mod plausibility;
+mod profiles;
+mod reload;
+mod rotation;
+mod schedule;
+mod scheduler;
+mod send;
+mod sensor_discovery;
 mod sensor_reader;
 mod sensor_readings;
+mod shutdown;
+mod test_pattern;
 
 use anyhow::{Context, Result};
-use ch_170::CH170Display;
+use ch_170::{CH170Display, DisplayMode};
+use clap::Parser;
+use cli::{Cli, Command};
+use config::{Config, DisplayValues};
+use reload::{ConfigWatcher, Runtime};
+use schedule::SystemClock;
+use scheduler::Ticker;
 use sensor_reader::SensorReader;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::thread::sleep;
-use std::time::Duration;
-use tracing::{error, info};
-
-// Constants
-const REFRESH_CYCLES_PER_MODE: u32 = 5;
+use sensor_readings::SensorReadings;
+use shutdown::Shutdown;
+use std::time::Instant;
+use tracing::{error, info, warn};
 
 fn main() -> Result<()> {
+    let cli = Cli::parse();
+
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    tracing_subscriber::fmt()
+        .with_max_level(cli.log_level)
+        .init();
+
+    let load_config = || -> Result<Config> {
+        let mut config =
+            Config::load(cli.config.as_deref()).context("Failed to load configuration")?;
+        if let Some(device) = &cli.device {
+            config.hid.device = Some(device.clone());
+        }
+        Ok(config)
+    };
+
+    match cli.command.clone().unwrap_or(Command::Run) {
+        Command::Run => run(&cli, load_config()?),
+        Command::ListDevices => list_devices::run(&load_config()?.hid),
+        Command::ListSensors { emit_config } => list_sensors::run(&load_config()?, emit_config),
+        Command::Send(args) => {
+            let mut display = open_display(&cli, &load_config()?, &Shutdown::new())?;
+            send::run(&mut display, &args)
+        }
+        Command::TestPattern => {
+            let shutdown = Shutdown::from_signals()?;
+            let mut display = open_display(&cli, &load_config()?, &shutdown)?;
+            test_pattern::run(&mut display, &shutdown)
+        }
+        Command::CheckConfig => check_config::run(cli.config.as_deref()),
+    }
+}
+
+/// Connect to the display, or print frames instead with `--dry-run`
+fn open_display(cli: &Cli, config: &Config, shutdown: &Shutdown) -> Result<CH170Display> {
+    if cli.dry_run {
+        return Ok(CH170Display::dry_run(&config.hid, shutdown));
+    }
+    CH170Display::new(&config.hid, shutdown).context("Failed to initialize CH170 display")
+}
 
+fn run(cli: &Cli, config: Config) -> Result<()> {
     info!("DeepCool CH170 Display Controller starting...");
 
-    // Setup graceful shutdown
-    let shutdown = setup_shutdown_handler()?;
+    // Setup graceful shutdown and config reloading
+    let shutdown = Shutdown::from_signals()?;
+    let watched_path = Config::locate(cli.config.as_deref())?;
+    let watcher = ConfigWatcher::new(watched_path.as_deref())?;
 
     // Initialize hardware connections
-    let mut sensor_reader = SensorReader::new().context("Failed to initialize sensor reader")?;
-    let mut display = CH170Display::new().context("Failed to initialize CH170 display")?;
+    let mut runtime = Runtime::new(&config)?;
+    let mut display = open_display(cli, &config, &shutdown)?;
 
     info!("Hardware initialized successfully");
 
     // Run main display update loop
-    run_display_loop(&mut sensor_reader, &mut display, &shutdown)?;
+    run_display_loop(&mut runtime, &mut display, &watcher, &shutdown)?;
+    runtime.sensor_reader.log_session_ranges();
 
     info!("DeepCool CH170 Display Controller stopped");
     Ok(())
 }
 
-fn setup_shutdown_handler() -> Result<Arc<AtomicBool>> {
-    let shutdown = Arc::new(AtomicBool::new(false));
-
-    signal_hook::flag::register(signal_hook::consts::SIGTERM, shutdown.clone())
-        .context("Failed to register SIGTERM handler")?;
-    signal_hook::flag::register(signal_hook::consts::SIGINT, shutdown.clone())
-        .context("Failed to register SIGINT handler")?;
-    signal_hook::flag::register(signal_hook::consts::SIGBREAK, shutdown.clone())
-        .context("Failed to register SIGBREAK handler")?;
-
-    info!("Shutdown handlers registered");
-    Ok(shutdown)
-}
-
 fn run_display_loop(
-    sensor_reader: &mut SensorReader,
+    runtime: &mut Runtime,
     display: &mut CH170Display,
-    shutdown: &Arc<AtomicBool>,
+    watcher: &ConfigWatcher,
+    shutdown: &Shutdown,
 ) -> Result<()> {
-    info!("Starting display update loop");
+    info!(values = ?runtime.display.values, "Starting display update loop");
 
-    while !shutdown.load(Ordering::Relaxed) {
-        run_mode_cycle(sensor_reader, display, shutdown);
-        // Switch to next display mode
-        display.switch_mode();
+    let mut ticker = Ticker::new(runtime.sample_period(), Instant::now());
+    while !shutdown.is_triggered() {
+        // Switch to the next display mode in the rotation
+        let (mode, refreshes) = runtime.next_mode();
+        run_mode_cycle(
+            runtime,
+            display,
+            watcher,
+            &mut ticker,
+            (mode, refreshes),
+            shutdown,
+        );
     }
 
+    ticker.log_stats();
     info!("Display update loop stopped");
     Ok(())
 }
 
 fn run_mode_cycle(
-    sensor_reader: &mut SensorReader,
+    runtime: &mut Runtime,
     display: &mut CH170Display,
-    shutdown: &Arc<AtomicBool>,
+    watcher: &ConfigWatcher,
+    ticker: &mut Ticker,
+    (mode, refreshes): (DisplayMode, u64),
+    shutdown: &Shutdown,
 ) {
-    let mut cycles = 0;
-    while !shutdown.load(Ordering::Relaxed) && cycles < REFRESH_CYCLES_PER_MODE {
-        // Update sensor readings
-        if let Err(err) = sensor_reader.update() {
-            error!(?err, "Failed to update sensor readings");
+    for _ in 0..refreshes {
+        if shutdown.is_triggered() {
+            return;
+        }
+        // Apply config changes between refreshes, never mid-sample, and
+        // restart the rotation under the new schedule
+        if watcher.take_request() && reload_config(runtime, watcher, ticker) {
+            return;
+        }
+        if switch_profile(runtime, ticker) {
+            return;
+        }
+        if apply_schedule(runtime, ticker) {
+            return;
         }
 
-        // Update display with current readings
-        if let Err(err) = display.update(sensor_reader.readings()) {
+        // Quiet hours: show zeros once, then leave the sensors and display alone
+        if runtime.zeros {
+            if !runtime.zeros_shown {
+                match display.update(&SensorReadings::default()) {
+                    Ok(()) => runtime.zeros_shown = true,
+                    Err(err) => error!(?err, "Failed to update display"),
+                }
+            }
+            wait_for_tick(ticker, &runtime.sensor_reader, shutdown);
+            continue;
+        }
+
+        // Sample sensor readings until the next display refresh
+        let Runtime {
+            sensor_reader,
+            pipeline,
+            rules,
+            display: display_config,
+            ..
+        } = runtime;
+        for sample in 0..pipeline.samples_per_refresh() {
+            if sample > 0 {
+                wait_for_tick(ticker, sensor_reader, shutdown);
+                if shutdown.is_triggered() {
+                    return;
+                }
+            }
+            if let Err(err) = sensor_reader.update() {
+                error!(?err, "Failed to update sensor readings");
+            }
+            pipeline.add_sample(sensor_reader.readings());
+        }
+
+        // Update display with processed current or peak readings, running the
+        // pipeline every tick so the filter state stays current either way
+        let processed = pipeline.refresh(sensor_reader.readings(), sensor_reader.sensor_values());
+        let readings = match display_config.values {
+            DisplayValues::Current => processed,
+            DisplayValues::Peak => pipeline.peak_readings(&sensor_reader.peak_readings()),
+        };
+
+        // Matching mode rules take over from the rotation, judged on the
+        // processed current readings
+        let shown = rules
+            .update(&processed, sensor_reader.sensor_values(), Instant::now())
+            .unwrap_or(mode);
+        display.set_mode(shown);
+        if let Err(err) = display.update(&readings) {
             error!(?err, "Failed to update display");
         }
 
-        cycles += 1;
+        wait_for_tick(ticker, sensor_reader, shutdown);
+    }
+}
 
-        // Sleep until next refresh
-        sleep(Duration::from_millis(sensor_reader.polling_period() as u64));
+/// Swap in the config from disk, keeping the current one if the new one is
+/// invalid. Returns whether the new config was applied.
+fn reload_config(runtime: &mut Runtime, watcher: &ConfigWatcher, ticker: &mut Ticker) -> bool {
+    match watcher.load().and_then(|config| runtime.reload(&config)) {
+        Ok(()) => {
+            ticker.set_period(runtime.sample_period(), Instant::now());
+            info!(
+                values = ?runtime.display.values,
+                polling_period_ms = runtime.sensor_reader.polling_period(),
+                "Configuration reloaded"
+            );
+            true
+        }
+        Err(err) => {
+            error!(?err, "Rejected new configuration, keeping the previous one");
+            false
+        }
+    }
+}
 
-        // Quick check for shutdown to be more responsive
-        if shutdown.load(Ordering::Relaxed) {
-            break;
+/// Apply the profile matching the running processes, returning whether it
+/// changed
+fn switch_profile(runtime: &mut Runtime, ticker: &mut Ticker) -> bool {
+    match runtime.update_profile(Instant::now()) {
+        Ok(true) => {
+            ticker.set_period(runtime.sample_period(), Instant::now());
+            true
         }
+        Ok(false) => false,
+        Err(err) => {
+            error!(?err, "Failed to switch profile, keeping the current one");
+            false
+        }
+    }
+}
+
+/// Apply the schedule window covering the current time, returning whether it
+/// changed
+fn apply_schedule(runtime: &mut Runtime, ticker: &mut Ticker) -> bool {
+    match runtime.update_schedule(&SystemClock) {
+        Ok(true) => {
+            ticker.set_period(runtime.sample_period(), Instant::now());
+            true
+        }
+        Ok(false) => false,
+        Err(err) => {
+            error!(
+                ?err,
+                "Failed to apply schedule window, keeping the current settings"
+            );
+            false
+        }
+    }
+}
+
+/// Sleep until the next sampling deadline, warning if ticks had to be skipped
+fn wait_for_tick(ticker: &mut Ticker, sensor_reader: &SensorReader, shutdown: &Shutdown) {
+    let skipped = ticker.wait(shutdown);
+    if skipped > 0 {
+        warn!(
+            skipped,
+            fetch_ms = sensor_reader.readings().elapsed_time_ms,
+            polling_period_ms = sensor_reader.polling_period(),
+            "Refresh fell behind schedule, skipped ticks"
+        );
     }
 }
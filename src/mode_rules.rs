@@ -0,0 +1,262 @@
+use crate::ch_170::DisplayMode;
+use crate::config::ModeRule;
+use crate::expression::Expr;
+use crate::sensor_readings::SensorReadings;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Picks a display mode from conditions on the readings, overriding the
+/// rotation while a rule matches
+pub struct ModeRules {
+    rules: Vec<Rule>,
+    /// Index of the rule currently shown, and since when
+    active: Option<(usize, Instant)>,
+}
+
+struct Rule {
+    mode: DisplayMode,
+    source: String,
+    condition: Expr,
+    hold: Duration,
+    priority: i32,
+    min_dwell: Duration,
+    /// When the condition last became true, while it still is
+    true_since: Option<Instant>,
+}
+
+impl ModeRules {
+    pub fn new(config: &[ModeRule]) -> Result<Self> {
+        let mut rules = Vec::new();
+        for rule in config {
+            let condition = Expr::parse(&rule.when).context(format!(
+                "Invalid condition for {} mode rule",
+                rule.mode.name()
+            ))?;
+            info!(
+                mode = rule.mode.name(),
+                when = rule.when,
+                for_seconds = rule.for_seconds,
+                priority = rule.priority,
+                min_dwell_seconds = rule.min_dwell_seconds,
+                "Display mode rule"
+            );
+            rules.push(Rule {
+                mode: rule.mode,
+                source: rule.when.clone(),
+                condition,
+                hold: Duration::from_secs(rule.for_seconds as u64),
+                priority: rule.priority,
+                min_dwell: Duration::from_secs(rule.min_dwell_seconds as u64),
+                true_since: None,
+            });
+        }
+        Ok(Self {
+            rules,
+            active: None,
+        })
+    }
+
+    /// Keep how long each unchanged rule's condition has held, so a rebuild
+    /// doesn't restart its `for_seconds`
+    pub fn inherit(&mut self, previous: &ModeRules) {
+        for rule in &mut self.rules {
+            if let Some(old) = previous
+                .rules
+                .iter()
+                .find(|old| old.mode == rule.mode && old.source == rule.source)
+            {
+                rule.true_since = old.true_since;
+            }
+        }
+    }
+
+    /// Raw LHM sensors the conditions read
+    pub fn sensor_ids(&self) -> Vec<&str> {
+        self.rules
+            .iter()
+            .flat_map(|rule| rule.condition.sensor_ids())
+            .collect()
+    }
+
+    /// Evaluate every rule against the readings at `now`, returning the mode to
+    /// show, or `None` to follow the rotation
+    pub fn update(
+        &mut self,
+        readings: &SensorReadings,
+        sensors: &HashMap<String, f64>,
+        now: Instant,
+    ) -> Option<DisplayMode> {
+        for rule in &mut self.rules {
+            if rule.condition.is_true(readings, sensors) {
+                rule.true_since.get_or_insert(now);
+            } else {
+                rule.true_since = None;
+            }
+        }
+
+        // Highest priority among the rules that have held long enough, the
+        // first listed on a tie
+        let best = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| {
+                rule.true_since
+                    .is_some_and(|since| now.saturating_duration_since(since) >= rule.hold)
+            })
+            .rev()
+            .max_by_key(|(_, rule)| rule.priority)
+            .map(|(index, _)| index);
+
+        if let Some((active, shown_since)) = self.active {
+            let rule = &self.rules[active];
+            let dwelling = now.saturating_duration_since(shown_since) < rule.min_dwell;
+            let preempted = best.is_some_and(|best| self.rules[best].priority > rule.priority);
+            if best == Some(active) || (dwelling && !preempted) {
+                return Some(rule.mode);
+            }
+        }
+
+        let previous = self.active.map(|(index, _)| index);
+        self.active = best.map(|index| (index, now));
+        match best {
+            Some(index) => {
+                let rule = &self.rules[index];
+                info!(
+                    mode = rule.mode.name(),
+                    when = rule.source,
+                    "Display mode rule matched"
+                );
+                Some(rule.mode)
+            }
+            None => {
+                if previous.is_some() {
+                    info!("No display mode rule matches, back to rotation");
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn rules(toml: &str) -> ModeRules {
+        ModeRules::new(&Config::from_toml(toml).unwrap().display.rules).unwrap()
+    }
+
+    fn readings(gpu_usage: f64, cpu_temp: f64) -> SensorReadings {
+        SensorReadings {
+            gpu_usage,
+            cpu_temp,
+            ..SensorReadings::default()
+        }
+    }
+
+    const RULES: &str = r#"
+        [[display.rules]]
+        mode = "gpu"
+        when = "gpu_usage > 50"
+        for_seconds = 5
+        min_dwell_seconds = 10
+
+        [[display.rules]]
+        mode = "cpu-fan"
+        when = "cpu_temp > 85"
+        priority = 10
+        min_dwell_seconds = 0
+    "#;
+
+    #[test]
+    fn test_condition_must_hold() {
+        let mut rules = rules(RULES);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let sensors = HashMap::new();
+
+        assert_eq!(rules.update(&readings(90.0, 60.0), &sensors, at(0)), None);
+        assert_eq!(rules.update(&readings(90.0, 60.0), &sensors, at(4)), None);
+        assert_eq!(
+            rules.update(&readings(90.0, 60.0), &sensors, at(5)),
+            Some(DisplayMode::Gpu)
+        );
+
+        // A dip resets the hold timer of a rule that isn't shown yet
+        let mut rules = self::rules(RULES);
+        rules.update(&readings(90.0, 60.0), &sensors, at(0));
+        rules.update(&readings(10.0, 60.0), &sensors, at(3));
+        assert_eq!(rules.update(&readings(90.0, 60.0), &sensors, at(6)), None);
+    }
+
+    #[test]
+    fn test_dwell_and_fallback() {
+        let mut rules = rules(RULES);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let sensors = HashMap::new();
+
+        rules.update(&readings(90.0, 60.0), &sensors, at(0));
+        assert_eq!(
+            rules.update(&readings(90.0, 60.0), &sensors, at(5)),
+            Some(DisplayMode::Gpu)
+        );
+        // Kept for the minimum dwell after the condition stops holding
+        assert_eq!(
+            rules.update(&readings(10.0, 60.0), &sensors, at(8)),
+            Some(DisplayMode::Gpu)
+        );
+        assert_eq!(
+            rules.update(&readings(10.0, 60.0), &sensors, at(14)),
+            Some(DisplayMode::Gpu)
+        );
+        assert_eq!(rules.update(&readings(10.0, 60.0), &sensors, at(15)), None);
+    }
+
+    #[test]
+    fn test_priority_preempts_dwell() {
+        let mut rules = rules(RULES);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let sensors = HashMap::new();
+
+        rules.update(&readings(90.0, 60.0), &sensors, at(0));
+        assert_eq!(
+            rules.update(&readings(90.0, 60.0), &sensors, at(5)),
+            Some(DisplayMode::Gpu)
+        );
+        assert_eq!(
+            rules.update(&readings(90.0, 90.0), &sensors, at(6)),
+            Some(DisplayMode::CpuFan)
+        );
+        // No dwell on the CPU fan rule, GPU is matching again once it cools
+        assert_eq!(
+            rules.update(&readings(90.0, 70.0), &sensors, at(7)),
+            Some(DisplayMode::Gpu)
+        );
+    }
+
+    #[test]
+    fn test_blank_values_never_match() {
+        let mut rules = rules(RULES);
+        let now = Instant::now();
+        assert_eq!(
+            rules.update(&readings(f64::NAN, f64::NAN), &HashMap::new(), now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_rules_rejected() {
+        let config =
+            Config::from_toml("[[display.rules]]\nmode = \"gpu\"\nwhen = \"gpu_usage >\"\n")
+                .unwrap();
+        assert!(ModeRules::new(&config.display.rules).is_err());
+
+        assert!(Config::from_toml("[[display.rules]]\nmode = \"gpu\"\n").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use crate::config::OversamplingConfig;
+use crate::sensor_readings::{Metric, SensorReadings};
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::info;
+
+/// Collects sensor samples between display refreshes and combines them per metric
+pub struct Oversampler {
+    config: OversamplingConfig,
+    samples_per_refresh: u32,
+    samples: HashMap<Metric, Vec<f64>>,
+}
+
+impl Oversampler {
+    /// The rate is checked against the LHM timeout when the config is parsed
+    pub fn new(config: &OversamplingConfig, refresh_period: Duration) -> Self {
+        let samples_per_refresh = match config.rate_hz {
+            None => 1,
+            Some(rate_hz) => {
+                ((refresh_period.as_secs_f64() * rate_hz.get() as f64).round() as u32).max(1)
+            }
+        };
+
+        if samples_per_refresh > 1 {
+            info!(samples_per_refresh, "Oversampling sensors");
+            for metric in Metric::ALL {
+                info!(
+                    metric = metric.name(),
+                    aggregation = ?config.aggregation(metric),
+                    "Oversampling aggregation"
+                );
+            }
+        }
+
+        Self {
+            config: config.clone(),
+            samples_per_refresh,
+            samples: HashMap::new(),
+        }
+    }
+
+    pub fn samples_per_refresh(&self) -> u32 {
+        self.samples_per_refresh
+    }
+
+    /// Record one sample, skipping blank (NaN) values
+    pub fn add(&mut self, readings: &SensorReadings) {
+        for metric in Metric::ALL {
+            let value = readings.get(metric);
+            if !value.is_nan() {
+                self.samples.entry(metric).or_default().push(value);
+            }
+        }
+    }
+
+    /// Combine the samples since the last call into `latest`, and start over
+    ///
+    /// Metrics without any sample keep their value from `latest`.
+    pub fn finish(&mut self, latest: &SensorReadings) -> SensorReadings {
+        let mut combined = *latest;
+        for (metric, samples) in self.samples.drain() {
+            if let Some(value) = self.config.aggregation(metric).apply(&samples) {
+                combined.set(metric, value);
+            }
+        }
+        combined
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn readings(cpu_temp: f64, cpu_power: f64) -> SensorReadings {
+        SensorReadings {
+            cpu_temp,
+            cpu_power,
+            ..SensorReadings::default()
+        }
+    }
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn oversampler(toml: &str) -> Oversampler {
+        let config = Config::from_toml(toml).unwrap();
+        Oversampler::new(&config.oversampling, SECOND)
+    }
+
+    #[test]
+    fn test_samples_per_refresh() {
+        assert_eq!(oversampler("").samples_per_refresh(), 1);
+        assert_eq!(
+            oversampler("[oversampling]\nrate_hz = 10").samples_per_refresh(),
+            10
+        );
+        assert_eq!(
+            oversampler("[oversampling]\nrate_hz = 4").samples_per_refresh(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_short_spike_survives_aggregation() {
+        let mut oversampler = oversampler("[oversampling]\nrate_hz = 10");
+        for (temp, power) in [(60.0, 80.0), (84.0, 140.0), (61.0, 90.0), (60.5, 90.0)] {
+            oversampler.add(&readings(temp, power));
+        }
+        let latest = readings(60.5, 90.0);
+        let combined = oversampler.finish(&latest);
+        assert_eq!(combined.cpu_temp, 84.0);
+        assert_eq!(combined.cpu_power, 100.0);
+
+        // The next refresh only sees its own samples
+        oversampler.add(&readings(f64::NAN, 70.0));
+        let combined = oversampler.finish(&readings(f64::NAN, 70.0));
+        assert!(combined.cpu_temp.is_nan());
+        assert_eq!(combined.cpu_power, 70.0);
+    }
+}
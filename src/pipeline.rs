@@ -0,0 +1,139 @@
+use crate::config::Config;
+use crate::derived::DerivedMetrics;
+use crate::filters::Filters;
+use crate::oversampling::Oversampler;
+use crate::sensor_readings::{Metric, SensorReadings};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Processing between the sensor reader and the display: samples are combined
+/// per refresh, derived metrics fill their slots, then smoothing filters run
+pub struct Pipeline {
+    oversampler: Oversampler,
+    derived: DerivedMetrics,
+    filters: Filters,
+    /// Highest processed value of each slot showing a derived metric
+    derived_peaks: HashMap<Metric, f64>,
+}
+
+impl Pipeline {
+    pub fn new(config: &Config, refresh_period: Duration) -> Result<Self> {
+        Ok(Self {
+            oversampler: Oversampler::new(&config.oversampling, refresh_period),
+            derived: DerivedMetrics::new(&config.derived, &config.slots)
+                .context("Invalid derived metric configuration")?,
+            filters: Filters::new(&config.filters),
+            derived_peaks: HashMap::new(),
+        })
+    }
+
+    /// Raw LHM sensors read by derived metrics, beyond the mapped ones
+    pub fn sensor_ids(&self) -> Vec<&str> {
+        self.derived.sensor_ids()
+    }
+
+    /// Whether `metric` is replaced by a derived metric rather than read
+    pub fn fills_slot(&self, metric: Metric) -> bool {
+        self.derived.fills_slot(metric)
+    }
+
+    /// Carry over the smoothing state and derived peaks of everything
+    /// configured the same as in `previous`
+    pub fn inherit(&mut self, previous: &Pipeline) {
+        self.filters.inherit(&previous.filters);
+        for (metric, peak) in &previous.derived_peaks {
+            if self.derived.slot_expr(*metric) == previous.derived.slot_expr(*metric) {
+                self.derived_peaks.insert(*metric, *peak);
+            }
+        }
+    }
+
+    pub fn samples_per_refresh(&self) -> u32 {
+        self.oversampler.samples_per_refresh()
+    }
+
+    pub fn add_sample(&mut self, readings: &SensorReadings) {
+        self.oversampler.add(readings);
+    }
+
+    /// Produce the readings for one display refresh from the samples since the last
+    pub fn refresh(
+        &mut self,
+        latest: &SensorReadings,
+        sensors: &HashMap<String, f64>,
+    ) -> SensorReadings {
+        let sampled = self.oversampler.finish(latest);
+        let derived = self.derived.apply(&sampled, sensors);
+        let processed = self.filters.apply(&derived);
+        for metric in Metric::ALL {
+            let value = processed.get(metric);
+            if self.derived.fills_slot(metric) && !value.is_nan() {
+                let peak = self.derived_peaks.entry(metric).or_insert(value);
+                *peak = peak.max(value);
+            }
+        }
+        processed
+    }
+
+    /// `peaks` of the sensor metrics with each derived slot replaced by the
+    /// peak of its own processed value, blank until it has one
+    pub fn peak_readings(&self, peaks: &SensorReadings) -> SensorReadings {
+        let mut readings = *peaks;
+        for metric in Metric::ALL {
+            if self.derived.fills_slot(metric) {
+                let peak = self.derived_peaks.get(&metric).copied();
+                readings.set(metric, peak.unwrap_or(f64::NAN));
+            }
+        }
+        readings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peak_of_derived_slot() {
+        let config = Config::from_toml(
+            r#"
+            [derived]
+            system_power = "cpu_power + gpu_power"
+
+            [slots]
+            gpu_power = "system_power"
+            "#,
+        )
+        .unwrap();
+        let mut pipeline = Pipeline::new(&config, Duration::from_secs(1)).unwrap();
+        let readings = |cpu_power, gpu_power| SensorReadings {
+            cpu_power,
+            gpu_power,
+            ..SensorReadings::default()
+        };
+
+        let sensor_peaks = readings(90.0, 250.0);
+        assert!(pipeline.peak_readings(&sensor_peaks).gpu_power.is_nan());
+
+        pipeline.refresh(&readings(90.0, 200.0), &HashMap::new());
+        pipeline.refresh(&readings(40.0, 250.0), &HashMap::new());
+        let peaks = pipeline.peak_readings(&sensor_peaks);
+        // The slot peaks at the highest sum, not at the sum of the peaks
+        assert_eq!(peaks.gpu_power, 290.0);
+        assert_eq!(peaks.cpu_power, 90.0);
+
+        // A rebuild keeps the peak while the slot shows the same expression
+        let mut rebuilt = Pipeline::new(&config, Duration::from_millis(500)).unwrap();
+        rebuilt.inherit(&pipeline);
+        assert_eq!(rebuilt.peak_readings(&sensor_peaks).gpu_power, 290.0);
+
+        let changed = Config::from_toml(
+            "[derived]\nsystem_power = \"cpu_power\"\n\n[slots]\ngpu_power = \"system_power\"\n",
+        )
+        .unwrap();
+        let mut rebuilt = Pipeline::new(&changed, Duration::from_secs(1)).unwrap();
+        rebuilt.inherit(&pipeline);
+        assert!(rebuilt.peak_readings(&sensor_peaks).gpu_power.is_nan());
+    }
+}
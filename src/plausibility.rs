@@ -0,0 +1,254 @@
+use crate::config::PlausibilityRule;
+use crate::sensor_readings::Metric;
+use std::collections::HashMap;
+use std::time::Instant;
+use tracing::{debug, info};
+
+/// Drops implausible samples per metric: values outside fixed bounds, and jumps
+/// faster than the allowed rate unless the new level persists
+pub struct Plausibility {
+    checks: HashMap<Metric, Check>,
+    /// Samples dropped per metric, kept across rule changes
+    dropped: HashMap<Metric, u64>,
+}
+
+struct Check {
+    rule: PlausibilityRule,
+    last_accepted: Option<(f64, Instant)>,
+    /// The first of the consecutive too-fast samples that agree with each
+    /// other, when it was read, and how many there have been
+    pending: Option<(f64, Instant, u32)>,
+}
+
+impl Plausibility {
+    /// `persist` is checked to be at least 2 when the config is parsed
+    pub fn new(rules: &HashMap<Metric, PlausibilityRule>) -> Self {
+        let mut checks = HashMap::new();
+        for metric in Metric::ALL {
+            let Some(rule) = rules.get(&metric) else {
+                continue;
+            };
+            info!(
+                metric = metric.name(),
+                min = ?rule.min,
+                max = ?rule.max,
+                max_rate = ?rule.max_rate,
+                persist = rule.persist,
+                "Plausibility rule"
+            );
+            checks.insert(
+                metric,
+                Check {
+                    rule: *rule,
+                    last_accepted: None,
+                    pending: None,
+                },
+            );
+        }
+        Self {
+            checks,
+            dropped: HashMap::new(),
+        }
+    }
+
+    /// Whether `value` lies within the metric's absolute bounds
+    pub fn within_bounds(&self, metric: Metric, value: f64) -> bool {
+        self.checks
+            .get(&metric)
+            .is_none_or(|check| check.within_bounds(value))
+    }
+
+    /// Pass `value` through if it is plausible, otherwise count it as dropped
+    pub fn check(&mut self, metric: Metric, value: f64, now: Instant) -> Option<f64> {
+        let Some(check) = self.checks.get_mut(&metric) else {
+            return Some(value);
+        };
+        let accepted = check.check(value, now);
+        if accepted.is_none() {
+            *self.dropped.entry(metric).or_default() += 1;
+            debug!(metric = metric.name(), value, "Dropped implausible sample");
+        }
+        accepted
+    }
+
+    /// Carry the drop counts over from checks built from an older config, and
+    /// the rate history of every rule that didn't change
+    pub fn inherit(&mut self, previous: &Plausibility) {
+        self.dropped.clone_from(&previous.dropped);
+        for (metric, check) in &mut self.checks {
+            if let Some(old) = previous
+                .checks
+                .get(metric)
+                .filter(|old| old.rule == check.rule)
+            {
+                check.last_accepted = old.last_accepted;
+                check.pending = old.pending;
+            }
+        }
+    }
+
+    pub fn log_dropped(&self) {
+        for metric in Metric::ALL {
+            if let Some(&dropped) = self.dropped.get(&metric) {
+                info!(
+                    metric = metric.name(),
+                    dropped, "Dropped implausible samples"
+                );
+            }
+        }
+    }
+}
+
+impl Check {
+    fn within_bounds(&self, value: f64) -> bool {
+        self.rule.min.is_none_or(|min| value >= min) && self.rule.max.is_none_or(|max| value <= max)
+    }
+
+    fn check(&mut self, value: f64, now: Instant) -> Option<f64> {
+        if !self.within_bounds(value) {
+            return None;
+        }
+
+        if let (Some(max_rate), Some((last, at))) = (self.rule.max_rate, self.last_accepted) {
+            let within_rate = |from: f64, since: Instant| {
+                (value - from).abs()
+                    <= max_rate * now.saturating_duration_since(since).as_secs_f64()
+            };
+            if !within_rate(last, at) {
+                // A jump is a real change once `persist` samples in a row read
+                // the new level, each within the rate of the first of them
+                let pending = match self.pending {
+                    Some((first, since, count)) if within_rate(first, since) => {
+                        (first, since, count + 1)
+                    }
+                    _ => (value, now, 1),
+                };
+                if pending.2 < self.rule.persist {
+                    self.pending = Some(pending);
+                    return None;
+                }
+            }
+        }
+
+        self.pending = None;
+        self.last_accepted = Some((value, now));
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::time::Duration;
+
+    fn plausibility(toml: &str) -> Plausibility {
+        Plausibility::new(&Config::from_toml(toml).unwrap().plausibility)
+    }
+
+    /// Feed one sample per second, returning what got through
+    fn run(plausibility: &mut Plausibility, metric: Metric, samples: &[f64]) -> Vec<Option<f64>> {
+        let start = Instant::now();
+        samples
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| {
+                plausibility.check(metric, value, start + Duration::from_secs(i as u64))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bounds_drop_glitches() {
+        let mut plausibility =
+            plausibility("[plausibility]\ncpu_cooler_rpm = { min = 200, max = 5000 }");
+        let output = run(
+            &mut plausibility,
+            Metric::CpuCoolerRpm,
+            &[1200.0, 65535.0, 1210.0, 0.0, 1190.0],
+        );
+        assert_eq!(
+            output,
+            [Some(1200.0), None, Some(1210.0), None, Some(1190.0)]
+        );
+        assert_eq!(plausibility.dropped[&Metric::CpuCoolerRpm], 2);
+        assert!(!plausibility.within_bounds(Metric::CpuCoolerRpm, 65535.0));
+        assert!(plausibility.within_bounds(Metric::CpuTemp, 255.0));
+    }
+
+    #[test]
+    fn test_spike_accepted_once_it_persists() {
+        let mut plausibility =
+            plausibility("[plausibility]\ncpu_temp = { max_rate = 5, persist = 3 }");
+        let output = run(
+            &mut plausibility,
+            Metric::CpuTemp,
+            &[50.0, 51.0, 95.0, 52.0, 80.0, 81.0, 82.0, 83.0],
+        );
+        assert_eq!(
+            output,
+            [
+                Some(50.0),
+                Some(51.0),
+                None,
+                Some(52.0),
+                None,
+                None,
+                Some(82.0),
+                Some(83.0)
+            ]
+        );
+        assert_eq!(plausibility.dropped[&Metric::CpuTemp], 3);
+    }
+
+    #[test]
+    fn test_disagreeing_glitches_never_persist() {
+        let mut plausibility =
+            plausibility("[plausibility]\ncpu_temp = { max_rate = 5, persist = 3 }");
+        let output = run(
+            &mut plausibility,
+            Metric::CpuTemp,
+            &[50.0, 51.0, 95.0, 10.0, 95.0, 52.0],
+        );
+        assert_eq!(
+            output,
+            [Some(50.0), Some(51.0), None, None, None, Some(52.0)]
+        );
+    }
+
+    #[test]
+    fn test_persist_must_be_at_least_two() {
+        for persist in [0, 1] {
+            let toml =
+                format!("[plausibility]\ncpu_temp = {{ max_rate = 5, persist = {persist} }}");
+            assert!(Config::from_toml(&toml).is_err());
+        }
+    }
+
+    #[test]
+    fn test_inherit_keeps_counts_and_history() {
+        let rule = "[plausibility]\ncpu_temp = { max_rate = 5, persist = 3 }";
+        let mut previous = plausibility(rule);
+        run(&mut previous, Metric::CpuTemp, &[50.0, 95.0]);
+
+        let mut next = plausibility(rule);
+        next.inherit(&previous);
+        assert_eq!(next.dropped[&Metric::CpuTemp], 1);
+        // The jump is still judged against the last accepted value
+        let later = Instant::now() + Duration::from_secs(2);
+        assert_eq!(next.check(Metric::CpuTemp, 95.0, later), None);
+
+        // Counts survive a rule being dropped, history doesn't
+        let mut next = plausibility("[plausibility]\ncpu_temp = { max_rate = 10 }");
+        next.inherit(&previous);
+        assert_eq!(next.dropped[&Metric::CpuTemp], 1);
+        assert!(next.checks[&Metric::CpuTemp].last_accepted.is_none());
+    }
+
+    #[test]
+    fn test_unconfigured_metrics_pass_through() {
+        let mut plausibility = plausibility("");
+        let output = run(&mut plausibility, Metric::GpuTemp, &[60.0, 255.0]);
+        assert_eq!(output, [Some(60.0), Some(255.0)]);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::config::Profile;
+use anyhow::{Context, Result};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+// Constants
+const PROCESS_SCAN_INTERVAL_SECS: u64 = 5;
+/// Linux truncates `/proc/<pid>/comm` to 15 bytes
+const COMM_LEN: usize = 15;
+
+/// Picks the profile to use from the running processes
+pub struct ProfileSelector {
+    profiles: Vec<(String, Vec<String>)>,
+    next_scan: Option<Instant>,
+}
+
+impl ProfileSelector {
+    /// Names are checked to be unique when the config is parsed
+    pub fn new(profiles: &[Profile]) -> Self {
+        Self {
+            profiles: profiles
+                .iter()
+                .map(|profile| (profile.name.clone(), profile.processes.clone()))
+                .collect(),
+            next_scan: None,
+        }
+    }
+
+    /// Keep the scan schedule of the selector this one replaces
+    pub fn inherit_schedule(&mut self, previous: &ProfileSelector) {
+        self.next_scan = previous.next_scan;
+    }
+
+    /// Scan the running processes if a scan is due, returning the first profile
+    /// with a running process, `Some(None)` if there is none, or `None` when no
+    /// scan was due
+    pub fn poll(&mut self, now: Instant) -> Option<Option<String>> {
+        if self.profiles.is_empty() || self.next_scan.is_some_and(|next| now < next) {
+            return None;
+        }
+        self.next_scan = Some(now + Duration::from_secs(PROCESS_SCAN_INTERVAL_SECS));
+
+        match running_processes() {
+            Ok(running) => Some(self.select(&running).map(str::to_string)),
+            Err(err) => {
+                warn!(?err, "Failed to list running processes");
+                None
+            }
+        }
+    }
+
+    fn select(&self, running: &[String]) -> Option<&str> {
+        self.profiles
+            .iter()
+            .find(|(_, patterns)| {
+                patterns.iter().any(|pattern| {
+                    running
+                        .iter()
+                        .any(|process| process_matches(pattern, process))
+                })
+            })
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Whether a running process name matches a configured one, ignoring case and
+/// `.exe`, and allowing for names truncated by the kernel
+fn process_matches(pattern: &str, process: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let process = process.to_ascii_lowercase();
+    let base = |name: &str| name.strip_suffix(".exe").unwrap_or(name).to_string();
+    base(&pattern) == base(&process) || (process.len() == COMM_LEN && pattern.starts_with(&process))
+}
+
+#[cfg(target_os = "linux")]
+fn running_processes() -> Result<Vec<String>> {
+    scan_proc(std::path::Path::new("/proc"))
+}
+
+/// Image names from a Toolhelp snapshot of the process list
+#[cfg(windows)]
+fn running_processes() -> Result<Vec<String>> {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+        CreateToolhelp32Snapshot, PROCESSENTRY32W, Process32FirstW, Process32NextW,
+        TH32CS_SNAPPROCESS,
+    };
+
+    // SAFETY: the snapshot handle is checked before use and closed once, and
+    // the entry is zeroed with `dwSize` set as the API requires
+    unsafe {
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error()).context("Failed to snapshot processes");
+        }
+        let mut entry: PROCESSENTRY32W = std::mem::zeroed();
+        entry.dwSize = size_of::<PROCESSENTRY32W>() as u32;
+
+        let mut names = Vec::new();
+        let mut found = Process32FirstW(snapshot, &mut entry);
+        while found != 0 {
+            let name = &entry.szExeFile;
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            names.push(String::from_utf16_lossy(&name[..len]));
+            found = Process32NextW(snapshot, &mut entry);
+        }
+        CloseHandle(snapshot);
+        Ok(names)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn running_processes() -> Result<Vec<String>> {
+    let output = std::process::Command::new("ps")
+        .args(["-A", "-o", "comm="])
+        .output()
+        .context("Failed to run ps")?;
+    Ok(parse_ps(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Read `<root>/<pid>/comm` of every process
+#[cfg(any(target_os = "linux", test))]
+fn scan_proc(root: &std::path::Path) -> Result<Vec<String>> {
+    let entries = std::fs::read_dir(root).context(format!("Failed to read {}", root.display()))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.parse::<u32>().is_ok())
+        })
+        // Processes can exit between listing and reading
+        .filter_map(|entry| std::fs::read_to_string(entry.path().join("comm")).ok())
+        .map(|comm| comm.trim_end().to_string())
+        .collect())
+}
+
+/// Executable names from `ps -A -o comm=`, which may be full paths
+#[cfg(any(not(any(target_os = "linux", windows)), test))]
+fn parse_ps(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.rsplit('/').next().unwrap_or(line).to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn selector(toml: &str) -> Result<ProfileSelector> {
+        Ok(ProfileSelector::new(&Config::from_toml(toml)?.profiles))
+    }
+
+    const PROFILES: &str = r#"
+        [[profiles]]
+        name = "gaming"
+        processes = ["Cyberpunk2077.exe", "blender"]
+
+        [[profiles]]
+        name = "compiling"
+        processes = ["rustc", "cc1plus"]
+    "#;
+
+    fn running(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_first_matching_profile_wins() {
+        let selector = selector(PROFILES).unwrap();
+        assert_eq!(selector.select(&running(&["systemd", "bash"])), None);
+        assert_eq!(
+            selector.select(&running(&["bash", "rustc"])),
+            Some("compiling")
+        );
+        assert_eq!(
+            selector.select(&running(&["rustc", "blender"])),
+            Some("gaming")
+        );
+    }
+
+    #[test]
+    fn test_process_name_matching() {
+        assert!(process_matches("Cyberpunk2077.exe", "cyberpunk2077.exe"));
+        assert!(process_matches("blender", "Blender.exe"));
+        // Truncated /proc comm names still match
+        assert!(process_matches("Cyberpunk2077.exe", "Cyberpunk2077.e"));
+        assert!(!process_matches("blender", "blend"));
+        assert!(!process_matches("rustc", "rustc-wrapper"));
+    }
+
+    #[test]
+    fn test_invalid_profiles_rejected() {
+        assert!(
+            selector(
+                "[[profiles]]\nname = \"a\"\nprocesses = [\"x\"]\n\n\
+                 [[profiles]]\nname = \"a\"\nprocesses = [\"y\"]\n"
+            )
+            .is_err()
+        );
+        assert!(selector("[[profiles]]\nname = \"a\"\nprocesses = []\n").is_err());
+    }
+
+    #[test]
+    fn test_no_scan_without_profiles() {
+        let mut selector = selector("").unwrap();
+        assert_eq!(selector.poll(Instant::now()), None);
+    }
+
+    #[test]
+    fn test_scan_proc() {
+        let root = std::env::temp_dir().join(format!("ch170-proc-{}", std::process::id()));
+        for (pid, comm) in [("1", "systemd\n"), ("4242", "blender\n")] {
+            std::fs::create_dir_all(root.join(pid)).unwrap();
+            std::fs::write(root.join(pid).join("comm"), comm).unwrap();
+        }
+        std::fs::create_dir_all(root.join("self")).unwrap();
+        std::fs::write(root.join("self").join("comm"), "ignored\n").unwrap();
+
+        let mut processes = scan_proc(&root).unwrap();
+        processes.sort();
+        assert_eq!(processes, ["blender", "systemd"]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_ps() {
+        assert_eq!(
+            parse_ps("/sbin/launchd\n  /Applications/Blender.app/Contents/MacOS/Blender\n"),
+            ["launchd", "Blender"]
+        );
+    }
+}
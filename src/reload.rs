@@ -0,0 +1,437 @@
+use crate::ch_170::DisplayMode;
+use crate::config::{Config, DisplayConfig};
+use crate::mode_rules::ModeRules;
+use crate::pipeline::Pipeline;
+use crate::profiles::ProfileSelector;
+use crate::rotation::ModeRotation;
+use crate::schedule::{self, Clock, Schedule};
+use crate::sensor_reader::SensorReader;
+use anyhow::{Context, Result};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Everything the display loop builds from the config, replaced as a unit when
+/// the config is reloaded, the profile changes or a schedule window starts or
+/// ends. The HID connection is not part of it.
+pub struct Runtime {
+    pub sensor_reader: SensorReader,
+    pub pipeline: Pipeline,
+    pub rotation: ModeRotation,
+    pub rules: ModeRules,
+    pub display: DisplayConfig,
+    /// Show zero readings instead of sensor data
+    pub zeros: bool,
+    /// Whether the zero readings frame has been sent
+    pub zeros_shown: bool,
+    /// The config as loaded, before any profile or window is applied
+    config: Config,
+    profile: Option<String>,
+    profiles: ProfileSelector,
+    schedule: Schedule,
+    /// Index of the schedule window in effect
+    window: Option<usize>,
+}
+
+impl Runtime {
+    pub fn new(config: &Config) -> Result<Self> {
+        let runtime = Self::build(config, None, None)?;
+        check_profiles(config)?;
+        Ok(runtime)
+    }
+
+    /// Build from `config` with the overrides of the named profile, then of the
+    /// schedule window at `window`, applied
+    pub fn build(base: &Config, profile: Option<&str>, window: Option<usize>) -> Result<Self> {
+        let profiles = ProfileSelector::new(&base.profiles);
+        let schedule = Schedule::new(&base.schedule).context("Invalid schedule")?;
+        let mut config = base.with_profile(profile);
+        if let Some(index) = window {
+            config = config.with_window(schedule.window(index));
+        }
+        let mut sensor_reader =
+            SensorReader::new(&config).context("Failed to initialize sensor reader")?;
+        let refresh_period = Duration::from_millis(sensor_reader.polling_period() as u64);
+        let pipeline = Pipeline::new(&config, refresh_period)?;
+        let rotation = ModeRotation::new(&config.display);
+        let rules = ModeRules::new(&config.display.rules).context("Invalid display mode rule")?;
+        sensor_reader.track_sensors(pipeline.sensor_ids());
+        sensor_reader.track_sensors(rules.sensor_ids());
+
+        Ok(Self {
+            sensor_reader,
+            pipeline,
+            rotation,
+            rules,
+            display: config.display,
+            zeros: window.is_some_and(|index| schedule.window(index).zeros),
+            zeros_shown: false,
+            config: base.clone(),
+            profile: profile.map(str::to_string),
+            profiles,
+            schedule,
+            window,
+        })
+    }
+
+    /// Rebuild from `config`, keeping the active profile if it still exists
+    ///
+    /// The schedule window is picked again on the next [`Runtime::update_schedule`].
+    /// If any part of the new config is invalid the current runtime is kept as is.
+    pub fn reload(&mut self, config: &Config) -> Result<()> {
+        let profile = self
+            .profile
+            .as_deref()
+            .filter(|name| config.profiles.iter().any(|profile| profile.name == *name));
+        let next = Self::build(config, profile, None)?;
+        check_profiles(config)?;
+        self.replace(next);
+        Ok(())
+    }
+
+    /// Switch profile if the running processes call for another one, returning
+    /// whether it changed
+    pub fn update_profile(&mut self, now: Instant) -> Result<bool> {
+        let Some(selected) = self.profiles.poll(now) else {
+            return Ok(false);
+        };
+        if selected == self.profile {
+            return Ok(false);
+        }
+
+        let next = Self::build(&self.config, selected.as_deref(), self.window).context(format!(
+            "Invalid profile {}",
+            selected.as_deref().unwrap_or("default")
+        ))?;
+        self.replace(next);
+        info!(
+            profile = self.profile.as_deref().unwrap_or("default"),
+            "Switched profile"
+        );
+        Ok(true)
+    }
+
+    /// Apply the schedule window covering the current time, returning whether
+    /// it changed
+    pub fn update_schedule(&mut self, clock: &impl Clock) -> Result<bool> {
+        let window = self.schedule.active(clock.now());
+        if window == self.window {
+            return Ok(false);
+        }
+
+        let next = Self::build(&self.config, self.profile.as_deref(), window)?;
+        if let Some(index) = self.window {
+            info!(
+                window = schedule::label(self.schedule.window(index)),
+                "Schedule window ended"
+            );
+        }
+        self.replace(next);
+        if let Some(index) = self.window {
+            info!(
+                window = schedule::label(self.schedule.window(index)),
+                zeros = self.zeros,
+                "Schedule window started"
+            );
+        }
+        Ok(true)
+    }
+
+    /// Swap in a rebuilt runtime, carrying the session statistics, processing
+    /// state, rule timers and process scan schedule over
+    fn replace(&mut self, mut next: Runtime) {
+        if self.zeros && next.zeros {
+            next.zeros_shown = self.zeros_shown;
+        }
+        next.sensor_reader.inherit_session(&self.sensor_reader);
+        next.pipeline.inherit(&self.pipeline);
+        next.rules.inherit(&self.rules);
+        next.profiles.inherit_schedule(&self.profiles);
+        *self = next;
+    }
+
+    pub fn refresh_period(&self) -> Duration {
+        Duration::from_millis(self.sensor_reader.polling_period() as u64)
+    }
+
+    /// Time between sensor samples, several per refresh when oversampling
+    pub fn sample_period(&self) -> Duration {
+        self.refresh_period() / self.pipeline.samples_per_refresh()
+    }
+
+    /// The next display mode and how many refreshes to show it for
+    pub fn next_mode(&mut self) -> (DisplayMode, u64) {
+        let Self {
+            sensor_reader,
+            pipeline,
+            rotation,
+            ..
+        } = self;
+        let (mode, duration) = rotation.next(|mode| {
+            mode.metrics()
+                .iter()
+                .any(|&metric| sensor_reader.has_data(metric) || pipeline.fills_slot(metric))
+        });
+
+        let refresh_ms = self.refresh_period().as_millis().max(1);
+        let refreshes = match duration {
+            Some(duration) => (duration.as_millis().div_ceil(refresh_ms) as u64).max(1),
+            None => u64::MAX,
+        };
+        (mode, refreshes)
+    }
+}
+
+/// Requests a config reload when the config file changes, or on SIGHUP on Unix
+pub struct ConfigWatcher {
+    path: Option<PathBuf>,
+    requested: Arc<AtomicBool>,
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl ConfigWatcher {
+    /// Watch `path`, the config file in use, if there is one
+    pub fn new(path: Option<&Path>) -> Result<Self> {
+        let requested = Arc::new(AtomicBool::new(false));
+
+        #[cfg(unix)]
+        signal_hook::flag::register(signal_hook::consts::SIGHUP, requested.clone())
+            .context("Failed to register SIGHUP handler")?;
+
+        let watcher = path
+            .map(|path| watch_file(path, requested.clone()))
+            .transpose()?;
+
+        Ok(Self {
+            path: path.map(Path::to_path_buf),
+            requested,
+            _watcher: watcher,
+        })
+    }
+
+    /// Whether a reload was requested since the last call
+    pub fn take_request(&self) -> bool {
+        self.requested.swap(false, Ordering::Relaxed)
+    }
+
+    /// Read the config again from the watched file
+    ///
+    /// Without a file the usual lookup runs again, picking up a newly created one.
+    pub fn load(&self) -> Result<Config> {
+        Config::load(self.path.as_deref())
+    }
+}
+
+/// Watch the file's directory rather than the file itself, since editors
+/// usually save by replacing the file
+fn watch_file(path: &Path, requested: Arc<AtomicBool>) -> Result<RecommendedWatcher> {
+    let file_name = path.file_name().map(ToOwned::to_owned);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event
+            && changes_file(&event, file_name.as_deref())
+        {
+            requested.store(true, Ordering::Relaxed);
+        }
+    })
+    .context("Failed to create config file watcher")?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .context(format!("Failed to watch {}", dir.display()))?;
+
+    info!(path = %path.display(), "Watching config file for changes");
+    Ok(watcher)
+}
+
+/// Whether `event` in the watched directory changed the file named `file_name`
+fn changes_file(event: &notify::Event, file_name: Option<&OsStr>) -> bool {
+    !event.kind.is_access()
+        && event
+            .paths
+            .iter()
+            .any(|changed| changed.file_name() == file_name)
+}
+
+/// Build every profile once, so a broken one is rejected with the rest of the
+/// config instead of failing each time its processes start
+fn check_profiles(config: &Config) -> Result<()> {
+    for profile in &config.profiles {
+        Runtime::build(config, Some(&profile.name), None)
+            .context(format!("Invalid profile {}", profile.name))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::Timestamp;
+
+    struct FixedClock(Timestamp);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> Timestamp {
+            self.0
+        }
+    }
+
+    fn wait_for_request(watcher: &ConfigWatcher) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if watcher.take_request() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    fn test_file_change_requests_reload() {
+        let dir = std::env::temp_dir().join(format!("ch170-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "[display]\nvalues = \"current\"\n").unwrap();
+
+        let watcher = ConfigWatcher::new(Some(&path)).unwrap();
+        assert!(!watcher.take_request());
+
+        std::fs::write(&path, "[display]\nvalues = \"peak\"\n").unwrap();
+        assert!(wait_for_request(&watcher));
+        let config = watcher.load().unwrap();
+        assert_eq!(config.display.values, crate::config::DisplayValues::Peak);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_only_changes_to_the_config_file_request_reload() {
+        use notify::event::{AccessKind, CreateKind, EventKind, ModifyKind, RemoveKind};
+
+        let event = |kind, path: &str| notify::Event::new(kind).add_path(PathBuf::from(path));
+        let config = Some(OsStr::new("config.toml"));
+
+        for kind in [
+            EventKind::Create(CreateKind::File),
+            EventKind::Modify(ModifyKind::Any),
+            EventKind::Remove(RemoveKind::File),
+        ] {
+            assert!(changes_file(&event(kind, "/etc/ch170/config.toml"), config));
+        }
+        // Editors reading the file and other files in the directory are ignored
+        let read = EventKind::Access(AccessKind::Any);
+        assert!(!changes_file(
+            &event(read, "/etc/ch170/config.toml"),
+            config
+        ));
+        let modify = EventKind::Modify(ModifyKind::Any);
+        assert!(!changes_file(
+            &event(modify, "/etc/ch170/other.toml"),
+            config
+        ));
+    }
+
+    #[test]
+    fn test_invalid_config_keeps_previous_runtime() {
+        let config = Config::from_toml("[lhm]\npolling_period_ms = 500\n").unwrap();
+        let mut runtime = Runtime::new(&config).unwrap();
+        assert_eq!(runtime.sample_period(), Duration::from_millis(500));
+        // 5 s per mode by default
+        assert_eq!(runtime.next_mode(), (DisplayMode::CpuFrequency, 10));
+
+        let invalid = Config::from_toml(
+            "[lhm]\npolling_period_ms = 250\n\n[derived]\nhot = \"cpu_temp >\"\n",
+        )
+        .unwrap();
+        assert!(runtime.reload(&invalid).is_err());
+        assert_eq!(runtime.sample_period(), Duration::from_millis(500));
+
+        let valid = Config::from_toml(
+            "[lhm]\npolling_period_ms = 250\n\n[display]\nfixed_mode = \"gpu\"\n",
+        )
+        .unwrap();
+        runtime.reload(&valid).unwrap();
+        assert_eq!(runtime.sample_period(), Duration::from_millis(250));
+        assert_eq!(runtime.next_mode(), (DisplayMode::Gpu, u64::MAX));
+    }
+
+    #[test]
+    fn test_invalid_profile_rejected() {
+        let toml = "[[profiles]]\nname = \"gaming\"\nprocesses = [\"game\"]\n\
+                    [[profiles.display.rules]]\nmode = \"gpu\"\nwhen = \"gpu_usage >\"\n";
+        let config = Config::from_toml(toml).unwrap();
+        let err = Runtime::new(&config).err().unwrap();
+        assert!(format!("{err:#}").starts_with("Invalid profile gaming"));
+
+        let mut runtime = Runtime::new(&Config::default()).unwrap();
+        assert!(runtime.reload(&config).is_err());
+    }
+
+    #[test]
+    fn test_schedule_window_overrides() {
+        let config = Config::from_toml(
+            r#"
+            [[display.rules]]
+            mode = "gpu"
+            when = "gpu_usage > 50"
+
+            [schedule]
+            time_zone = "UTC"
+            windows = [
+                { start = "22:00", end = "07:00", zeros = true, polling_period_ms = 5000 },
+                { start = "12:00", end = "13:00", mode = "cpu-fan" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let mut runtime = Runtime::new(&config).unwrap();
+        let clock = |timestamp: &str| FixedClock(timestamp.parse().unwrap());
+
+        assert!(
+            !runtime
+                .update_schedule(&clock("2026-10-16T10:00:00Z"))
+                .unwrap()
+        );
+        assert!(!runtime.zeros);
+
+        assert!(
+            runtime
+                .update_schedule(&clock("2026-10-16T23:00:00Z"))
+                .unwrap()
+        );
+        assert!(runtime.zeros);
+        assert_eq!(runtime.sample_period(), Duration::from_secs(5));
+        assert!(
+            !runtime
+                .update_schedule(&clock("2026-10-17T01:00:00Z"))
+                .unwrap()
+        );
+
+        // A forced mode also switches the mode rules off
+        assert!(
+            runtime
+                .update_schedule(&clock("2026-10-17T12:30:00Z"))
+                .unwrap()
+        );
+        assert!(!runtime.zeros);
+        assert_eq!(runtime.sample_period(), Duration::from_secs(1));
+        assert_eq!(runtime.next_mode(), (DisplayMode::CpuFan, u64::MAX));
+        assert!(runtime.rules.sensor_ids().is_empty());
+        assert!(runtime.display.rules.is_empty());
+
+        assert!(
+            runtime
+                .update_schedule(&clock("2026-10-17T13:00:00Z"))
+                .unwrap()
+        );
+        assert_eq!(runtime.display.rules.len(), 1);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::ch_170::DisplayMode;
+use crate::config::DisplayConfig;
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// Chooses which display mode to show next, and for how long
+pub struct ModeRotation {
+    slots: Vec<(DisplayMode, Duration)>,
+    fixed: Option<DisplayMode>,
+    skip_unavailable: bool,
+    index: usize,
+}
+
+impl ModeRotation {
+    /// An empty rotation without a fixed mode is rejected when the config is parsed
+    pub fn new(config: &DisplayConfig) -> Self {
+        if let Some(mode) = config.fixed_mode {
+            info!(mode = mode.name(), "Showing a fixed display mode");
+        } else {
+            let schedule: Vec<_> = config
+                .rotation
+                .iter()
+                .map(|slot| format!("{} {}s", slot.mode.name(), slot.seconds))
+                .collect();
+            info!(
+                ?schedule,
+                skip_unavailable = config.skip_unavailable,
+                "Display mode rotation"
+            );
+        }
+
+        Self {
+            slots: config
+                .rotation
+                .iter()
+                .map(|slot| (slot.mode, Duration::from_secs(slot.seconds.get() as u64)))
+                .collect(),
+            fixed: config.fixed_mode,
+            skip_unavailable: config.skip_unavailable,
+            index: 0,
+        }
+    }
+
+    /// The next mode and how long to show it, `None` for as long as the
+    /// config stays the same
+    ///
+    /// Modes for which `available` is false are passed over, unless no mode is
+    /// available at all, e.g. before the first sensor read.
+    pub fn next(
+        &mut self,
+        available: impl Fn(DisplayMode) -> bool,
+    ) -> (DisplayMode, Option<Duration>) {
+        if let Some(mode) = self.fixed {
+            return (mode, None);
+        }
+
+        let len = self.slots.len();
+        let chosen = (0..len)
+            .map(|offset| (self.index + offset) % len)
+            .find(|&index| !self.skip_unavailable || available(self.slots[index].0))
+            .unwrap_or(self.index);
+        if chosen != self.index {
+            debug!(
+                skipped = self.slots[self.index].0.name(),
+                "Skipping display mode without data"
+            );
+        }
+
+        self.index = (chosen + 1) % len;
+        let (mode, duration) = self.slots[chosen];
+        (mode, Some(duration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use anyhow::Result;
+
+    fn build(toml: &str) -> Result<ModeRotation> {
+        Ok(ModeRotation::new(&Config::from_toml(toml)?.display))
+    }
+
+    fn modes(rotation: &mut ModeRotation, available: impl Fn(DisplayMode) -> bool) -> Vec<&str> {
+        (0..4).map(|_| rotation.next(&available).0.name()).collect()
+    }
+
+    #[test]
+    fn test_default_rotation() {
+        let mut rotation = build("").unwrap();
+        assert_eq!(
+            modes(&mut rotation, |_| true),
+            ["cpu-frequency", "gpu", "cpu-fan", "cpu-frequency"]
+        );
+        assert_eq!(rotation.next(|_| true).1, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_configured_order_and_durations() {
+        let mut rotation = build(
+            r#"
+            [display]
+            rotation = [
+                { mode = "gpu", seconds = 20 },
+                { mode = "cpu-frequency", seconds = 5 },
+            ]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            rotation.next(|_| true),
+            (DisplayMode::Gpu, Some(Duration::from_secs(20)))
+        );
+        assert_eq!(
+            rotation.next(|_| true),
+            (DisplayMode::CpuFrequency, Some(Duration::from_secs(5)))
+        );
+        assert_eq!(rotation.next(|_| true).0, DisplayMode::Gpu);
+    }
+
+    #[test]
+    fn test_unavailable_modes_skipped() {
+        let no_gpu = |mode| mode != DisplayMode::Gpu;
+
+        let mut rotation = build("").unwrap();
+        assert_eq!(
+            modes(&mut rotation, no_gpu),
+            ["cpu-frequency", "cpu-fan", "cpu-frequency", "cpu-fan"]
+        );
+
+        // With nothing available the rotation carries on as configured
+        let mut rotation = build("").unwrap();
+        assert_eq!(
+            modes(&mut rotation, |_| false),
+            ["cpu-frequency", "gpu", "cpu-fan", "cpu-frequency"]
+        );
+
+        let mut rotation = build("[display]\nskip_unavailable = false").unwrap();
+        assert_eq!(
+            modes(&mut rotation, no_gpu),
+            ["cpu-frequency", "gpu", "cpu-fan", "cpu-frequency"]
+        );
+    }
+
+    #[test]
+    fn test_fixed_mode() {
+        let mut rotation = build("[display]\nfixed_mode = \"cpu-fan\"").unwrap();
+        assert_eq!(rotation.next(|_| false), (DisplayMode::CpuFan, None));
+        assert_eq!(rotation.next(|_| true), (DisplayMode::CpuFan, None));
+    }
+
+    #[test]
+    fn test_invalid_rotation_rejected() {
+        assert!(build("[display]\nrotation = []").is_err());
+        assert!(build("[display]\nrotation = []\nfixed_mode = \"gpu\"").is_ok());
+        assert!(
+            Config::from_toml("[display]\nrotation = [{ mode = \"gpu\", seconds = 0 }]").is_err()
+        );
+        assert!(Config::from_toml("[display]\nfixed_mode = \"psu\"").is_err());
+    }
+}
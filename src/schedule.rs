@@ -0,0 +1,236 @@
+use crate::config::{ScheduleConfig, ScheduleWindow};
+use anyhow::{Context, Result};
+use jiff::Timestamp;
+use jiff::civil::{DateTime, Weekday};
+use jiff::tz::TimeZone;
+use serde::Deserialize;
+use tracing::info;
+
+/// Source of the current time, swapped for a fixed one in tests
+pub trait Clock {
+    fn now(&self) -> Timestamp;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        Timestamp::now()
+    }
+}
+
+/// Day of the week a schedule window starts on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Day {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Day {
+    pub const ALL: [Day; 7] = [
+        Day::Mon,
+        Day::Tue,
+        Day::Wed,
+        Day::Thu,
+        Day::Fri,
+        Day::Sat,
+        Day::Sun,
+    ];
+
+    fn weekday(self) -> Weekday {
+        match self {
+            Day::Mon => Weekday::Monday,
+            Day::Tue => Weekday::Tuesday,
+            Day::Wed => Weekday::Wednesday,
+            Day::Thu => Weekday::Thursday,
+            Day::Fri => Weekday::Friday,
+            Day::Sat => Weekday::Saturday,
+            Day::Sun => Weekday::Sunday,
+        }
+    }
+}
+
+/// Finds the schedule window covering a point in time
+///
+/// Windows are matched on local wall-clock time, so they follow DST changes: a
+/// window starting in the hour skipped in spring starts when the clocks go
+/// forward, and one covering the hour repeated in autumn covers it twice.
+pub struct Schedule {
+    time_zone: TimeZone,
+    windows: Vec<ScheduleWindow>,
+}
+
+impl Schedule {
+    pub fn new(config: &ScheduleConfig) -> Result<Self> {
+        let time_zone = match &config.time_zone {
+            Some(name) => TimeZone::get(name).context(format!("Unknown time zone {name}"))?,
+            None => TimeZone::system(),
+        };
+        for window in &config.windows {
+            info!(
+                window = label(window),
+                days = ?window.days,
+                zeros = window.zeros,
+                polling_period_ms = window.polling_period_ms,
+                mode = window.mode.map(|mode| mode.name()),
+                "Schedule window"
+            );
+        }
+
+        Ok(Self {
+            time_zone,
+            windows: config.windows.clone(),
+        })
+    }
+
+    /// Index of the first window covering `now`, if any
+    pub fn active(&self, now: Timestamp) -> Option<usize> {
+        let local = now.to_zoned(self.time_zone.clone()).datetime();
+        self.windows.iter().position(|window| covers(window, local))
+    }
+
+    pub fn window(&self, index: usize) -> &ScheduleWindow {
+        &self.windows[index]
+    }
+}
+
+/// Start and end time, e.g. `22:00-07:00`
+pub fn label(window: &ScheduleWindow) -> String {
+    format!(
+        "{}-{}",
+        window.start.strftime("%H:%M"),
+        window.end.strftime("%H:%M")
+    )
+}
+
+fn covers(window: &ScheduleWindow, local: DateTime) -> bool {
+    let starts_on = |weekday: Weekday| window.days.iter().any(|day| day.weekday() == weekday);
+    let time = local.time();
+    if window.start < window.end {
+        starts_on(local.weekday()) && window.start <= time && time < window.end
+    } else {
+        // Past midnight the window belongs to the day it started on
+        (starts_on(local.weekday()) && time >= window.start)
+            || (starts_on(local.weekday().previous()) && time < window.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn schedule(toml: &str) -> Result<Schedule> {
+        Schedule::new(&Config::from_toml(toml)?.schedule)
+    }
+
+    fn at(timestamp: &str) -> Timestamp {
+        timestamp.parse().unwrap()
+    }
+
+    #[test]
+    fn test_window_past_midnight() {
+        let schedule = schedule(
+            r#"
+            [schedule]
+            time_zone = "UTC"
+            windows = [{ days = ["fri"], start = "22:00", end = "07:00", zeros = true }]
+            "#,
+        )
+        .unwrap();
+        // 2026-10-16 is a Friday
+        assert_eq!(schedule.active(at("2026-10-16T21:59:00Z")), None);
+        assert_eq!(schedule.active(at("2026-10-16T22:00:00Z")), Some(0));
+        assert_eq!(schedule.active(at("2026-10-17T06:59:59Z")), Some(0));
+        assert_eq!(schedule.active(at("2026-10-17T07:00:00Z")), None);
+        assert_eq!(schedule.active(at("2026-10-17T23:00:00Z")), None);
+        assert_eq!(schedule.active(at("2026-10-15T23:00:00Z")), None);
+    }
+
+    #[test]
+    fn test_first_listed_window_wins() {
+        let schedule = schedule(
+            r#"
+            [schedule]
+            time_zone = "UTC"
+
+            [[schedule.windows]]
+            days = ["sat", "sun"]
+            start = "09:00"
+            end = "12:00"
+            mode = "gpu"
+
+            [[schedule.windows]]
+            start = "08:00"
+            end = "18:00"
+            polling_period_ms = 5000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(schedule.active(at("2026-10-17T10:00:00Z")), Some(0));
+        assert_eq!(schedule.active(at("2026-10-16T10:00:00Z")), Some(1));
+        assert_eq!(schedule.active(at("2026-10-17T13:00:00Z")), Some(1));
+    }
+
+    #[test]
+    fn test_dst_changes() {
+        let schedule = schedule(
+            r#"
+            [schedule]
+            time_zone = "Europe/Berlin"
+            windows = [{ start = "22:00", end = "07:00", zeros = true }]
+            "#,
+        )
+        .unwrap();
+
+        // Clocks go forward at 02:00 CET on 2026-03-29, so 07:00 is 05:00 UTC
+        assert_eq!(schedule.active(at("2026-03-29T04:59:00Z")), Some(0));
+        assert_eq!(schedule.active(at("2026-03-29T05:00:00Z")), None);
+        // and clocks go back at 03:00 CEST on 2026-10-25, making it 06:00 UTC
+        assert_eq!(schedule.active(at("2026-10-25T05:59:00Z")), Some(0));
+        assert_eq!(schedule.active(at("2026-10-25T06:00:00Z")), None);
+
+        let schedule = self::schedule(
+            r#"
+            [schedule]
+            time_zone = "Europe/Berlin"
+            windows = [{ start = "02:30", end = "04:00", mode = "cpu-fan" }]
+            "#,
+        )
+        .unwrap();
+        // 02:30 doesn't exist in spring, the window starts with the jump to 03:00
+        assert_eq!(schedule.active(at("2026-03-29T00:59:59Z")), None);
+        assert_eq!(schedule.active(at("2026-03-29T01:00:00Z")), Some(0));
+        // 02:30 to 03:00 happens twice in autumn
+        assert_eq!(schedule.active(at("2026-10-25T00:45:00Z")), Some(0));
+        assert_eq!(schedule.active(at("2026-10-25T01:45:00Z")), Some(0));
+    }
+
+    #[test]
+    fn test_invalid_schedule_rejected() {
+        assert!(schedule("[schedule]\ntime_zone = \"Mars/Olympus_Mons\"\n").is_err());
+        assert!(
+            schedule("[schedule]\nwindows = [{ start = \"08:00\", end = \"08:00\" }]\n").is_err()
+        );
+        assert!(
+            schedule("[schedule]\nwindows = [{ days = [], start = \"08:00\", end = \"09:00\" }]\n")
+                .is_err()
+        );
+        assert!(
+            Config::from_toml("[schedule]\nwindows = [{ start = \"25:00\", end = \"08:00\" }]\n")
+                .is_err()
+        );
+        assert!(
+            Config::from_toml(
+                "[schedule]\nwindows = [{ days = [\"monday\"], start = \"07:00\", end = \"08:00\" }]\n"
+            )
+            .is_err()
+        );
+    }
+}
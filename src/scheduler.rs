@@ -0,0 +1,133 @@
+use crate::shutdown::Shutdown;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Fixed-rate scheduler that wakes on deadlines `start + n * period`
+///
+/// Time spent fetching and writing between waits doesn't push later ticks back.
+/// When a tick is missed entirely it's skipped rather than run back-to-back.
+pub struct Ticker {
+    period: Duration,
+    next: Instant,
+    stats: TickStats,
+}
+
+/// How late ticks woke up relative to their deadline
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TickStats {
+    pub ticks: u64,
+    pub skipped: u64,
+    pub total_lateness: Duration,
+    pub max_lateness: Duration,
+}
+
+impl TickStats {
+    pub fn mean_lateness(&self) -> Duration {
+        match self.ticks {
+            0 => Duration::ZERO,
+            ticks => self.total_lateness / ticks as u32,
+        }
+    }
+}
+
+impl Ticker {
+    pub fn new(period: Duration, start: Instant) -> Self {
+        Self {
+            period,
+            next: start + period,
+            stats: TickStats::default(),
+        }
+    }
+
+    /// Switch to a new period, with the next deadline one period after `now`
+    ///
+    /// Jitter statistics carry over.
+    pub fn set_period(&mut self, period: Duration, now: Instant) {
+        self.period = period;
+        self.next = now + period;
+    }
+
+    /// Sleep until the next deadline, returning how many ticks were skipped
+    ///
+    /// Returns early without ticking if shutdown is triggered.
+    pub fn wait(&mut self, shutdown: &Shutdown) -> u64 {
+        if shutdown.sleep_until(self.next) {
+            return 0;
+        }
+        self.tick(Instant::now())
+    }
+
+    /// Record a wake-up at `now` and schedule the next deadline after it
+    fn tick(&mut self, now: Instant) -> u64 {
+        let lateness = now.saturating_duration_since(self.next);
+        let missed = (lateness.as_nanos() / self.period.as_nanos().max(1)) as u64;
+
+        self.stats.ticks += 1;
+        self.stats.skipped += missed;
+        self.stats.total_lateness += lateness;
+        self.stats.max_lateness = self.stats.max_lateness.max(lateness);
+        self.next += self.period * (missed + 1) as u32;
+        missed
+    }
+
+    pub fn log_stats(&self) {
+        let stats = self.stats;
+        info!(
+            ticks = stats.ticks,
+            skipped = stats.skipped,
+            mean_lateness_ms = stats.mean_lateness().as_secs_f64() * 1000.0,
+            max_lateness_ms = stats.max_lateness.as_secs_f64() * 1000.0,
+            "Polling jitter"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(1000);
+
+    #[test]
+    fn test_deadlines_do_not_drift() {
+        let start = Instant::now();
+        let mut ticker = Ticker::new(PERIOD, start);
+
+        // Waking 30 ms late doesn't delay the following deadline
+        assert_eq!(ticker.tick(start + Duration::from_millis(1030)), 0);
+        assert_eq!(ticker.next, start + 2 * PERIOD);
+        assert_eq!(ticker.tick(start + Duration::from_millis(2010)), 0);
+        assert_eq!(ticker.next, start + 3 * PERIOD);
+
+        let stats = ticker.stats;
+        assert_eq!(stats.ticks, 2);
+        assert_eq!(stats.max_lateness, Duration::from_millis(30));
+        assert_eq!(stats.mean_lateness(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_missed_ticks_are_skipped() {
+        let start = Instant::now();
+        let mut ticker = Ticker::new(PERIOD, start);
+
+        // A 2.5 s stall misses the deadlines at 2 s and 3 s
+        assert_eq!(ticker.tick(start + Duration::from_millis(3500)), 2);
+        assert_eq!(ticker.next, start + 4 * PERIOD);
+        assert_eq!(ticker.stats.skipped, 2);
+    }
+
+    #[test]
+    fn test_period_change_keeps_stats() {
+        let start = Instant::now();
+        let mut ticker = Ticker::new(PERIOD, start);
+        ticker.tick(start + Duration::from_millis(1040));
+
+        let now = start + Duration::from_millis(1500);
+        ticker.set_period(Duration::from_millis(250), now);
+        assert_eq!(ticker.next, now + Duration::from_millis(250));
+        assert_eq!(ticker.tick(now + Duration::from_millis(260)), 0);
+        assert_eq!(ticker.next, now + Duration::from_millis(500));
+        assert_eq!(ticker.stats.ticks, 2);
+        assert_eq!(ticker.stats.max_lateness, Duration::from_millis(40));
+    }
+}
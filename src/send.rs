@@ -0,0 +1,45 @@
+use crate::ch_170::CH170Display;
+use crate::cli::SendArgs;
+use crate::sensor_readings::SensorReadings;
+use anyhow::{Context, Result};
+use tracing::info;
+
+/// Write one frame built from the values on the command line
+pub fn run(display: &mut CH170Display, args: &SendArgs) -> Result<()> {
+    let readings = readings_from(args);
+    display.set_mode(args.mode);
+    display
+        .update(&readings)
+        .context("Failed to send frame to display")?;
+
+    info!(mode = args.mode.name(), ?readings, "Sent frame");
+    Ok(())
+}
+
+fn readings_from(args: &SendArgs) -> SensorReadings {
+    let mut readings = SensorReadings::default();
+    for &(metric, value) in &args.values {
+        readings.set(metric, value);
+    }
+    readings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ch_170::DisplayMode;
+    use crate::sensor_readings::Metric;
+
+    #[test]
+    fn test_readings_from_values() {
+        let args = SendArgs {
+            mode: DisplayMode::CpuFan,
+            values: vec![(Metric::CpuTemp, 65.0), (Metric::CpuCoolerRpm, 1200.0)],
+        };
+        let readings = readings_from(&args);
+        assert_eq!(readings.cpu_temp, 65.0);
+        assert_eq!(readings.cpu_cooler_rpm, 1200.0);
+        // Values not given are sent as 0
+        assert_eq!(readings.gpu_temp, 0.0);
+    }
+}
@@ -0,0 +1,399 @@
+use crate::config::DeviceSelector;
+use crate::lhm_data::LHMDataChildren;
+use crate::sensor_readings::Metric;
+
+/// Which hardware nodes a discovery rule considers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HardwareClass {
+    Cpu,
+    Gpu,
+    Any,
+}
+
+impl HardwareClass {
+    fn matches(&self, hardware_type: &str) -> bool {
+        match self {
+            HardwareClass::Cpu => matches!(hardware_type, "amdcpu" | "intelcpu" | "cpu"),
+            HardwareClass::Gpu => hardware_type.starts_with("gpu-"),
+            HardwareClass::Any => true,
+        }
+    }
+}
+
+/// How the sensor for a metric is recognised when no explicit ID is configured
+struct DiscoveryRule {
+    hardware: HardwareClass,
+    kind: &'static str,
+    /// Preferred sensor names in priority order, matched case-insensitively as substrings
+    names: &'static [&'static str],
+    /// Whether to take the first sensor of the right kind when no name matches
+    fallback_to_first: bool,
+}
+
+fn discovery_rule(metric: Metric) -> DiscoveryRule {
+    match metric {
+        Metric::CpuTemp => DiscoveryRule {
+            hardware: HardwareClass::Cpu,
+            kind: "temperature",
+            names: &["Package", "Tctl/Tdie", "Tctl", "Tdie"],
+            fallback_to_first: true,
+        },
+        Metric::CpuPower => DiscoveryRule {
+            hardware: HardwareClass::Cpu,
+            kind: "power",
+            names: &["Package"],
+            fallback_to_first: true,
+        },
+        Metric::CpuUsage => DiscoveryRule {
+            hardware: HardwareClass::Cpu,
+            kind: "load",
+            names: &["CPU Total"],
+            fallback_to_first: true,
+        },
+        Metric::CpuFreq => DiscoveryRule {
+            hardware: HardwareClass::Cpu,
+            kind: "clock",
+            names: &["Cores (Average)", "Core #1"],
+            fallback_to_first: false,
+        },
+        Metric::CpuCoolerRpm => DiscoveryRule {
+            hardware: HardwareClass::Any,
+            kind: "fan",
+            names: &["CPU"],
+            fallback_to_first: false,
+        },
+        Metric::GpuTemp => DiscoveryRule {
+            hardware: HardwareClass::Gpu,
+            kind: "temperature",
+            names: &["GPU Core"],
+            fallback_to_first: true,
+        },
+        Metric::GpuPower => DiscoveryRule {
+            hardware: HardwareClass::Gpu,
+            kind: "power",
+            names: &["GPU Package", "GPU Power"],
+            fallback_to_first: true,
+        },
+        Metric::GpuUsage => DiscoveryRule {
+            hardware: HardwareClass::Gpu,
+            kind: "load",
+            names: &["GPU Core"],
+            fallback_to_first: true,
+        },
+        Metric::GpuFreq => DiscoveryRule {
+            hardware: HardwareClass::Gpu,
+            kind: "clock",
+            names: &["GPU Core"],
+            fallback_to_first: true,
+        },
+    }
+}
+
+/// Which devices of a metric's hardware class to discover sensors on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceFilter<'a> {
+    /// The first device with a sensor for any metric of the class, so every
+    /// metric of a class is read from the same device
+    First,
+    /// Every device with a matching sensor
+    All,
+    /// One device picked by index or name
+    Selected(&'a DeviceSelector),
+}
+
+/// Pick the sensor for `metric` on the first device of its class
+pub fn discover_sensor(computer: &LHMDataChildren, metric: Metric) -> Option<&LHMDataChildren> {
+    discover_sensors(computer, metric, DeviceFilter::First)
+        .into_iter()
+        .next()
+}
+
+/// Pick the sensors for `metric` from the LHM tree rooted at `computer`, at
+/// most one per device
+///
+/// Fan metrics aren't tied to a device class and always yield the single best match.
+pub fn discover_sensors<'a>(
+    computer: &'a LHMDataChildren,
+    metric: Metric,
+    filter: DeviceFilter,
+) -> Vec<&'a LHMDataChildren> {
+    let rule = discovery_rule(metric);
+    if rule.hardware == HardwareClass::Any {
+        return select_sensor(&rule, computer).into_iter().collect();
+    }
+
+    let devices: Vec<&LHMDataChildren> = computer
+        .hardware()
+        .into_iter()
+        .filter(|hardware| {
+            hardware
+                .hardware_type()
+                .is_some_and(|hardware_type| rule.hardware.matches(hardware_type))
+        })
+        .collect();
+
+    match filter {
+        DeviceFilter::First => devices
+            .iter()
+            .find(|device| serves_class(rule.hardware, device))
+            .and_then(|device| select_sensor(&rule, device))
+            .into_iter()
+            .collect(),
+        DeviceFilter::All => devices
+            .iter()
+            .filter_map(|device| select_sensor(&rule, device))
+            .collect(),
+        DeviceFilter::Selected(DeviceSelector::Index(index)) => devices
+            .get(*index)
+            .and_then(|device| select_sensor(&rule, device))
+            .into_iter()
+            .collect(),
+        DeviceFilter::Selected(DeviceSelector::Name(name)) => {
+            let name = name.to_lowercase();
+            devices
+                .iter()
+                .find(|device| device.text.to_lowercase().contains(&name))
+                .and_then(|device| select_sensor(&rule, device))
+                .into_iter()
+                .collect()
+        }
+    }
+}
+
+/// Whether `device` has a sensor for any metric of `class`
+fn serves_class(class: HardwareClass, device: &LHMDataChildren) -> bool {
+    Metric::ALL
+        .into_iter()
+        .map(discovery_rule)
+        .filter(|rule| rule.hardware == class)
+        .any(|rule| select_sensor(&rule, device).is_some())
+}
+
+/// Apply `rule`'s sensor kind and name preferences within one device
+fn select_sensor<'a>(
+    rule: &DiscoveryRule,
+    device: &'a LHMDataChildren,
+) -> Option<&'a LHMDataChildren> {
+    let candidates: Vec<&LHMDataChildren> = device
+        .sensors()
+        .into_iter()
+        .filter(|sensor| sensor.sensor_kind() == Some(rule.kind))
+        .collect();
+
+    for name in rule.names {
+        let name = name.to_lowercase();
+        if let Some(sensor) = candidates
+            .iter()
+            .find(|sensor| sensor.text.to_lowercase().contains(&name))
+        {
+            return Some(sensor);
+        }
+    }
+
+    if rule.fallback_to_first {
+        candidates.first().copied()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lhm_data::LHMData;
+
+    fn computer() -> LHMDataChildren {
+        let data: LHMData =
+            serde_json::from_str(include_str!("../testdata/lhm-pre-release.json")).unwrap();
+        data.children.into_iter().next().unwrap()
+    }
+
+    fn discovered_id(computer: &LHMDataChildren, metric: Metric) -> Option<&str> {
+        discover_sensor(computer, metric).and_then(|sensor| sensor.sensor_id.as_deref())
+    }
+
+    #[test]
+    fn test_discovers_intel_cpu_sensors() {
+        let computer = computer();
+        assert_eq!(
+            discovered_id(&computer, Metric::CpuTemp),
+            Some("/intelcpu/0/temperature/12")
+        );
+        assert_eq!(
+            discovered_id(&computer, Metric::CpuPower),
+            Some("/intelcpu/0/power/0")
+        );
+        assert_eq!(
+            discovered_id(&computer, Metric::CpuUsage),
+            Some("/intelcpu/0/load/0")
+        );
+        assert_eq!(
+            discovered_id(&computer, Metric::CpuFreq),
+            Some("/intelcpu/0/clock/13")
+        );
+    }
+
+    #[test]
+    fn test_discovers_gpu_sensors() {
+        let computer = computer();
+        assert_eq!(
+            discovered_id(&computer, Metric::GpuTemp),
+            Some("/gpu-nvidia/0/temperature/0")
+        );
+        assert_eq!(
+            discovered_id(&computer, Metric::GpuPower),
+            Some("/gpu-nvidia/0/power/0")
+        );
+        assert_eq!(
+            discovered_id(&computer, Metric::GpuUsage),
+            Some("/gpu-nvidia/0/load/0")
+        );
+        assert_eq!(
+            discovered_id(&computer, Metric::GpuFreq),
+            Some("/gpu-nvidia/0/clock/0")
+        );
+    }
+
+    #[test]
+    fn test_discovers_cpu_fan_by_name() {
+        let computer = computer();
+        assert_eq!(
+            discovered_id(&computer, Metric::CpuCoolerRpm),
+            Some("/lpc/nct6798d/0/fan/1")
+        );
+    }
+
+    fn dual_socket() -> LHMDataChildren {
+        let data: LHMData = serde_json::from_str(
+            r#"{"Children": [{"Text": "NODE", "Value": "", "Children": [
+                {"Text": "Intel Xeon Gold 6248", "Value": "", "HardwareId": "/intelcpu/0", "Children": [
+                    {"Text": "Powers", "Value": "", "Children": [
+                        {"Text": "CPU Package", "Value": "120 W", "SensorId": "/intelcpu/0/power/0", "Children": []}
+                    ]}
+                ]},
+                {"Text": "Intel Xeon Gold 6248", "Value": "", "HardwareId": "/intelcpu/1", "Children": [
+                    {"Text": "Powers", "Value": "", "Children": [
+                        {"Text": "CPU Package", "Value": "95 W", "SensorId": "/intelcpu/1/power/0", "Children": []}
+                    ]}
+                ]},
+                {"Text": "Intel UHD Graphics", "Value": "", "HardwareId": "/gpu-intel-integrated/0", "Children": [
+                    {"Text": "Load", "Value": "", "Children": [
+                        {"Text": "D3D 3D", "Value": "3 %", "SensorId": "/gpu-intel-integrated/0/load/0", "Children": []}
+                    ]}
+                ]},
+                {"Text": "NVIDIA RTX A6000", "Value": "", "HardwareId": "/gpu-nvidia/0", "Children": [
+                    {"Text": "Powers", "Value": "", "Children": [
+                        {"Text": "GPU Package", "Value": "250 W", "SensorId": "/gpu-nvidia/0/power/0", "Children": []}
+                    ]}
+                ]},
+                {"Text": "NVIDIA RTX A6000", "Value": "", "HardwareId": "/gpu-nvidia/1", "Children": [
+                    {"Text": "Powers", "Value": "", "Children": [
+                        {"Text": "GPU Package", "Value": "210 W", "SensorId": "/gpu-nvidia/1/power/0", "Children": []}
+                    ]}
+                ]}
+            ]}]}"#,
+        )
+        .unwrap();
+        data.children.into_iter().next().unwrap()
+    }
+
+    fn discovered_ids<'a>(
+        computer: &'a LHMDataChildren,
+        metric: Metric,
+        filter: DeviceFilter,
+    ) -> Vec<&'a str> {
+        discover_sensors(computer, metric, filter)
+            .into_iter()
+            .filter_map(|sensor| sensor.sensor_id.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn test_discovers_all_devices() {
+        let computer = dual_socket();
+        assert_eq!(
+            discovered_ids(&computer, Metric::CpuPower, DeviceFilter::All),
+            ["/intelcpu/0/power/0", "/intelcpu/1/power/0"]
+        );
+        assert_eq!(
+            discovered_ids(&computer, Metric::GpuPower, DeviceFilter::All),
+            ["/gpu-nvidia/0/power/0", "/gpu-nvidia/1/power/0"]
+        );
+    }
+
+    #[test]
+    fn test_first_device_serves_every_metric_of_its_class() {
+        // The integrated GPU is listed first and only has a load sensor, so
+        // GPU power is left undiscovered rather than read from another GPU
+        let computer = dual_socket();
+        let gpu_ids: Vec<&str> = [
+            Metric::GpuTemp,
+            Metric::GpuPower,
+            Metric::GpuUsage,
+            Metric::GpuFreq,
+        ]
+        .into_iter()
+        .flat_map(|metric| discovered_ids(&computer, metric, DeviceFilter::First))
+        .collect();
+        assert_eq!(gpu_ids, ["/gpu-intel-integrated/0/load/0"]);
+
+        // Devices without any sensor of the class are skipped
+        let data: LHMData = serde_json::from_str(
+            r#"{"Children": [{"Text": "PC", "Value": "", "Children": [
+                {"Text": "Intel UHD Graphics", "Value": "", "HardwareId": "/gpu-intel-integrated/0", "Children": []},
+                {"Text": "NVIDIA RTX A6000", "Value": "", "HardwareId": "/gpu-nvidia/0", "Children": [
+                    {"Text": "GPU Core", "Value": "61.0 °C", "SensorId": "/gpu-nvidia/0/temperature/0", "Children": []},
+                    {"Text": "GPU Package", "Value": "250 W", "SensorId": "/gpu-nvidia/0/power/0", "Children": []}
+                ]},
+                {"Text": "NVIDIA RTX A6000", "Value": "", "HardwareId": "/gpu-nvidia/1", "Children": [
+                    {"Text": "GPU Core", "Value": "74 %", "SensorId": "/gpu-nvidia/1/load/0", "Children": []}
+                ]}
+            ]}]}"#,
+        )
+        .unwrap();
+        let computer = data.children.into_iter().next().unwrap();
+        let gpu_ids: Vec<&str> = [
+            Metric::GpuTemp,
+            Metric::GpuPower,
+            Metric::GpuUsage,
+            Metric::GpuFreq,
+        ]
+        .into_iter()
+        .flat_map(|metric| discovered_ids(&computer, metric, DeviceFilter::First))
+        .collect();
+        assert_eq!(
+            gpu_ids,
+            ["/gpu-nvidia/0/temperature/0", "/gpu-nvidia/0/power/0"]
+        );
+    }
+
+    #[test]
+    fn test_selects_device_by_index_or_name() {
+        let computer = dual_socket();
+        let second = DeviceSelector::Index(1);
+        assert_eq!(
+            discovered_ids(&computer, Metric::CpuPower, DeviceFilter::Selected(&second)),
+            ["/intelcpu/1/power/0"]
+        );
+
+        let by_name = DeviceSelector::Name("rtx a6000".to_string());
+        assert_eq!(
+            discovered_ids(
+                &computer,
+                Metric::GpuPower,
+                DeviceFilter::Selected(&by_name)
+            ),
+            ["/gpu-nvidia/0/power/0"]
+        );
+
+        let missing = DeviceSelector::Index(5);
+        assert!(
+            discovered_ids(
+                &computer,
+                Metric::CpuPower,
+                DeviceFilter::Selected(&missing)
+            )
+            .is_empty()
+        );
+    }
+}
@@ -1,160 +1,176 @@
-use crate::sensor_readings::{SensorReadings, TemperatureUnit};
+use crate::config::{Aggregation, Calibration, Config, DeviceSelection, StaleConfig, StalePolicy};
+use crate::lhm_data::{LHMData, LHMDataChildren, Schema};
+use crate::plausibility::Plausibility;
+use crate::sensor_discovery::{DeviceFilter, discover_sensors};
+use crate::sensor_readings::{Metric, SensorReadings};
 use anyhow::{Context, Result};
-use serde::Deserialize;
-use std::time::Duration;
-use tracing::debug;
-
-// Configuration Constants
-const LHM_API_URL: &str = "http://127.0.0.1:8085/data.json";
-const TIMEOUT_MS: u64 = 100;
-const POLLING_PERIOD_MS: u32 = 1000;
-
-// Sensor Identifiers
-const CPU_IDENTIFIER: &str = "/amdcpu/0";
-const CPU_TEMPERATURE_NAME: &str = "/amdcpu/0/temperature/2";
-const CPU_POWER_IDENTIFIER: &str = "/amdcpu/0/power/0";
-const CPU_USAGE_IDENTIFIER: &str = "/amdcpu/0/load/0";
-const CPU_FREQUENCY_IDENTIFIER: &str = "/amdcpu/0/clock/2";
-const MOTHERBOARD_IDENTIFIER: &str = "/motherboard";
-const CPU_FAN_IDENTIFIER: &str = "/lpc/nct6701d/0/fan/1";
-const GPU_IDENTIFIER: &str = "/gpu-nvidia/0";
-const GPU_TEMPERATURE_NAME: &str = "/gpu-nvidia/0/temperature/0";
-const GPU_POWER_IDENTIFIER: &str = "/gpu-nvidia/0/power/0";
-const GPU_USAGE_IDENTIFIER: &str = "/gpu-nvidia/0/load/0";
-const GPU_FREQUENCY_IDENTIFIER: &str = "/gpu-nvidia/0/clock/0";
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
 
 pub struct SensorReader {
     client: reqwest::blocking::Client,
+    url: reqwest::Url,
+    credentials: Option<(String, Option<String>)>,
+    sensor_ids: Vec<(String, Metric)>,
+    devices: DeviceSelection,
+    aggregation: HashMap<Metric, Aggregation>,
+    calibration: HashMap<Metric, Calibration>,
+    plausibility: Plausibility,
+    undiscovered: Vec<Metric>,
+    /// Sensor IDs found by discovery, kept apart from the configured ones
+    discovered: HashMap<Metric, Vec<String>>,
+    /// Metrics already warned about for having no matching sensor
+    discovery_reported: HashSet<Metric>,
+    schema: Option<Schema>,
     readings: SensorReadings,
+    session_ranges: HashMap<Metric, MetricRange>,
+    lhm_max: HashMap<Metric, f64>,
+    stale: StaleConfig,
+    last_updated: HashMap<Metric, Instant>,
+    stale_metrics: HashSet<Metric>,
+    /// Raw values of sensors read by derived metrics, missing while unavailable
+    sensor_values: HashMap<String, f64>,
+    tracked_sensors: Vec<String>,
+}
+
+/// Lowest and highest value observed for a metric
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl MetricRange {
+    fn include(range: Option<MetricRange>, val: f64) -> MetricRange {
+        match range {
+            Some(range) => MetricRange {
+                min: range.min.min(val),
+                max: range.max.max(val),
+            },
+            None => MetricRange { min: val, max: val },
+        }
+    }
 }
 
 impl SensorReader {
-    pub fn new() -> Result<Self> {
+    pub fn new(config: &Config) -> Result<Self> {
+        let lhm = &config.lhm;
+        let url = reqwest::Url::parse(&lhm.url)
+            .context(format!("Invalid LHM endpoint URL: {}", lhm.url))?;
         let client = reqwest::blocking::Client::builder()
-            .timeout(Duration::from_millis(TIMEOUT_MS))
+            .timeout(Duration::from_millis(lhm.timeout_ms))
             .build()
             .context("Failed to create HTTP client for LHM")?;
+        let credentials = lhm
+            .username
+            .clone()
+            .map(|username| (username, lhm.password.clone()));
+
+        info!(
+            url = %url,
+            timeout_ms = lhm.timeout_ms,
+            basic_auth = credentials.is_some(),
+            "LHM endpoint"
+        );
+
+        let mut sensor_ids = Vec::new();
+        let mut undiscovered = Vec::new();
+        for metric in Metric::ALL {
+            let mapped = config.sensors.sensor_ids(metric);
+            if mapped.is_empty() {
+                undiscovered.push(metric);
+            }
+            for sensor_id in mapped {
+                info!(metric = metric.name(), sensor_id, "Sensor mapping");
+                sensor_ids.push((sensor_id.to_string(), metric));
+            }
+
+            let aggregation = config.aggregation(metric);
+            if aggregation != Aggregation::default() {
+                info!(
+                    metric = metric.name(),
+                    ?aggregation,
+                    "Aggregating across devices"
+                );
+            }
+            if let Some(selector) = config.devices.selector(metric) {
+                info!(metric = metric.name(), ?selector, "Device selection");
+            }
+            if let Some(calibration) = config.sensors.calibration.get(&metric) {
+                info!(
+                    metric = metric.name(),
+                    offset = calibration.offset,
+                    multiplier = calibration.multiplier,
+                    min = ?calibration.min,
+                    max = ?calibration.max,
+                    "Sensor calibration"
+                );
+            }
+        }
 
         Ok(Self {
             client,
+            url,
+            credentials,
+            sensor_ids,
+            devices: config.devices.clone(),
+            aggregation: config.aggregation.clone(),
+            calibration: config.sensors.calibration.clone(),
+            plausibility: Plausibility::new(&config.plausibility),
+            undiscovered,
+            discovered: HashMap::new(),
+            discovery_reported: HashSet::new(),
+            schema: None,
             readings: SensorReadings {
-                cpu_temp: 0.0,
-                cpu_power: 0.0,
-                cpu_usage: 0.0,
-                cpu_freq: 0.0,
-                cpu_cooler_rpm: 0.0,
-                gpu_temp: 0.0,
-                gpu_power: 0.0,
-                gpu_usage: 0.0,
-                gpu_freq: 0.0,
-                elapsed_time_ms: 0,
-                polling_period: POLLING_PERIOD_MS,
-                all_temperature_unit: TemperatureUnit::Celsius,
+                polling_period: config.lhm.polling_period_ms.get(),
+                ..SensorReadings::default()
             },
+            session_ranges: HashMap::new(),
+            lhm_max: HashMap::new(),
+            stale: config.stale,
+            last_updated: HashMap::new(),
+            stale_metrics: HashSet::new(),
+            sensor_values: HashMap::new(),
+            tracked_sensors: Vec::new(),
         })
     }
 
-    pub fn update(&mut self) -> Result<()> {
-        let sensor_reading = &mut self.readings;
-
-        let start = std::time::Instant::now();
-        let data: LHMData = self
-            .client
-            .get(LHM_API_URL)
+    /// Fetch the full sensor tree from the LHM web server, with sensor IDs
+    /// synthesized if the server uses the legacy schema
+    pub fn fetch(&self) -> Result<(LHMData, Schema)> {
+        let mut request = self.client.get(self.url.clone());
+        if let Some((username, password)) = &self.credentials {
+            request = request.basic_auth(username, password.as_ref());
+        }
+        let mut data: LHMData = request
             .send()
-            .context("Failed to fetch LHM data")?
+            .context(format!("Failed to fetch LHM data from {}", self.url))?
+            .error_for_status()
+            .context(format!("LHM web server at {} returned an error", self.url))?
             .json()
-            .context("Failed to parse LHM data")?;
-        let computer = data
-            .children
-            .get(0)
-            .context("No computer data found in LHM response")?;
-        for hardware in &computer.children {
-            let Some(hardware_id) = hardware.hardware_id.as_deref() else {
-                continue;
-            };
-            match hardware_id {
-                MOTHERBOARD_IDENTIFIER => {
-                    let Some(mb) = hardware.children.get(0) else {
-                        continue;
-                    };
-                    let Some(mb_fans) = mb.children.get(2).map(|x| &x.children) else {
-                        continue;
-                    };
-                    for sensor in mb_fans.iter() {
-                        match sensor.sensor_id.as_deref() {
-                            Some(CPU_FAN_IDENTIFIER) => {
-                                if let Some(val) = sensor.value.as_rpm() {
-                                    sensor_reading.cpu_cooler_rpm = val
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                CPU_IDENTIFIER => {
-                    let sensor_iterator = hardware.children.iter().flat_map(|x| x.children.iter());
-                    for sensor in sensor_iterator {
-                        match sensor.sensor_id.as_deref() {
-                            Some(CPU_TEMPERATURE_NAME) => {
-                                if let Some((val, unit)) = sensor.value.as_temperature() {
-                                    sensor_reading.cpu_temp = val;
-                                    sensor_reading.all_temperature_unit = unit;
-                                }
-                            }
-                            Some(CPU_FREQUENCY_IDENTIFIER) => {
-                                if let Some(val) = sensor.value.as_frequency() {
-                                    sensor_reading.cpu_freq = val;
-                                }
-                            }
-                            Some(CPU_POWER_IDENTIFIER) => {
-                                if let Some(val) = sensor.value.as_power() {
-                                    sensor_reading.cpu_power = val;
-                                }
-                            }
-                            Some(CPU_USAGE_IDENTIFIER) => {
-                                if let Some(val) = sensor.value.as_usage() {
-                                    sensor_reading.cpu_usage = val;
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                GPU_IDENTIFIER => {
-                    let sensor_iterator = hardware.children.iter().flat_map(|x| x.children.iter());
-                    for sensor in sensor_iterator {
-                        match sensor.sensor_id.as_deref() {
-                            Some(GPU_TEMPERATURE_NAME) => {
-                                if let Some((val, unit)) = sensor.value.as_temperature() {
-                                    sensor_reading.gpu_temp = val;
-                                    sensor_reading.all_temperature_unit = unit;
-                                }
-                            }
-                            Some(GPU_FREQUENCY_IDENTIFIER) => {
-                                if let Some(val) = sensor.value.as_frequency() {
-                                    sensor_reading.gpu_freq = val;
-                                }
-                            }
-                            Some(GPU_POWER_IDENTIFIER) => {
-                                if let Some(val) = sensor.value.as_power() {
-                                    sensor_reading.gpu_power = val;
-                                }
-                            }
-                            Some(GPU_USAGE_IDENTIFIER) => {
-                                if let Some(val) = sensor.value.as_usage() {
-                                    sensor_reading.gpu_usage = val;
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                _ => {}
-            }
+            .context(format!("Failed to parse LHM data from {}", self.url))?;
+        let schema = data.normalize();
+        Ok((data, schema))
+    }
+
+    pub fn update(&mut self) -> Result<()> {
+        let result = self.fetch_and_apply();
+        // Also runs when the fetch failed, so an unreachable LHM goes stale too
+        self.check_staleness(Instant::now());
+        result
+    }
+
+    fn fetch_and_apply(&mut self) -> Result<()> {
+        let start = Instant::now();
+        let (data, schema) = self.fetch()?;
+        if self.schema != Some(schema) {
+            info!(?schema, "Detected LHM data schema");
+            self.schema = Some(schema);
         }
+        self.apply_data(&data)?;
         let elapsed = start.elapsed();
-        sensor_reading.elapsed_time_ms = elapsed.as_millis() as u64;
+        self.readings.elapsed_time_ms = elapsed.as_millis() as u64;
 
         debug!(
             cpu_temp = self.readings.cpu_temp,
@@ -173,142 +189,563 @@ impl SensorReader {
         Ok(())
     }
 
-    pub fn polling_period(&self) -> u32 {
-        self.readings.polling_period
-    }
+    fn apply_data(&mut self, data: &LHMData) -> Result<()> {
+        let computer = data
+            .children
+            .first()
+            .context("No computer data found in LHM response")?;
+        if !self.undiscovered.is_empty() {
+            self.discover_sensors(computer);
+        }
+        // Sensors are matched by ID anywhere in the tree, since Super I/O chips
+        // nest their groups under the motherboard in board-specific order
+        let sensors: HashMap<&str, &LHMDataChildren> = computer
+            .sensors()
+            .into_iter()
+            .filter_map(|sensor| Some((sensor.sensor_id.as_deref()?, sensor)))
+            .collect();
 
-    pub fn readings(&self) -> &SensorReadings {
-        &self.readings
-    }
-}
+        for sensor_id in &self.tracked_sensors {
+            match sensors
+                .get(sensor_id.as_str())
+                .and_then(|sensor| sensor.value.as_number())
+            {
+                Some(val) => self.sensor_values.insert(sensor_id.clone(), val),
+                None => self.sensor_values.remove(sensor_id),
+            };
+        }
 
-#[derive(Debug, Deserialize)]
-struct LHMData {
-    #[serde(rename = "Children")]
-    children: Vec<LHMDataChildren>,
-}
+        let mut values: HashMap<Metric, Vec<f64>> = HashMap::new();
+        let mut maxes: HashMap<Metric, Vec<f64>> = HashMap::new();
+        for (sensor_id, metric) in &self.sensor_ids {
+            let Some(sensor) = sensors.get(sensor_id.as_str()) else {
+                continue;
+            };
+            // Any quantity can be shown in any slot; temperatures also carry their unit
+            if let Some((_, unit)) = sensor.value.as_temperature() {
+                self.readings.all_temperature_unit = unit;
+            }
+            if let Some(val) = sensor.value.as_number() {
+                values.entry(*metric).or_default().push(val);
+            }
+            if let Some(max) = sensor.max.as_number() {
+                maxes.entry(*metric).or_default().push(max);
+            }
+        }
 
-#[derive(Debug)]
-enum LHMDataValue {
-    Temperature(f64, TemperatureUnit),
-    Power(f64),
-    Usage(f64),
-    Frequency(f64),
-    Rpm(f64),
-    Unknown,
-}
+        let now = Instant::now();
+        for metric in Metric::ALL {
+            let aggregation = self.aggregation.get(&metric).copied().unwrap_or_default();
+            let calibration = self.calibration.get(&metric).copied().unwrap_or_default();
+            if let Some(val) = values
+                .get(&metric)
+                .and_then(|values| aggregation.apply(values))
+                .map(|val| calibration.apply(val))
+                .and_then(|val| self.plausibility.check(metric, val, now))
+            {
+                self.readings.set(metric, val);
+                self.last_updated.insert(metric, now);
+                let range = self.session_ranges.get(&metric).copied();
+                self.session_ranges
+                    .insert(metric, MetricRange::include(range, val));
+            }
+            if let Some(max) = maxes
+                .get(&metric)
+                .and_then(|maxes| aggregation.apply(maxes))
+                .map(|max| calibration.apply(max))
+                .filter(|max| self.plausibility.within_bounds(metric, *max))
+            {
+                self.lhm_max.insert(metric, max);
+            }
+        }
+        Ok(())
+    }
 
-impl LHMDataValue {
-    fn as_temperature(&self) -> Option<(f64, TemperatureUnit)> {
-        if let LHMDataValue::Temperature(val, unit) = self {
-            Some((*val, *unit))
-        } else {
-            None
+    /// Select sensors for metrics without a configured ID, retrying on later polls
+    /// for devices LHM has not enumerated yet
+    fn discover_sensors(&mut self, computer: &LHMDataChildren) {
+        let mut still_undiscovered = Vec::new();
+        for metric in std::mem::take(&mut self.undiscovered) {
+            let discovered = discover_sensors(computer, metric, self.device_filter(metric));
+            if discovered.is_empty() {
+                still_undiscovered.push(metric);
+            }
+            for sensor in discovered {
+                let Some(sensor_id) = sensor.sensor_id.as_deref() else {
+                    continue;
+                };
+                info!(
+                    metric = metric.name(),
+                    sensor_id,
+                    name = sensor.text.as_str(),
+                    "Discovered sensor"
+                );
+                self.sensor_ids.push((sensor_id.to_string(), metric));
+                self.discovered
+                    .entry(metric)
+                    .or_default()
+                    .push(sensor_id.to_string());
+            }
+        }
+
+        for metric in &still_undiscovered {
+            if self.discovery_reported.insert(*metric) {
+                warn!(
+                    metric = metric.name(),
+                    "No matching sensor found, metric will read 0"
+                );
+            }
         }
+        self.undiscovered = still_undiscovered;
     }
 
-    fn as_power(&self) -> Option<f64> {
-        if let LHMDataValue::Power(val) = self {
-            Some(*val)
-        } else {
-            None
+    fn device_filter(&self, metric: Metric) -> DeviceFilter<'_> {
+        match self.devices.selector(metric) {
+            Some(selector) => DeviceFilter::Selected(selector),
+            None if self.aggregation.get(&metric).copied().unwrap_or_default()
+                == Aggregation::First =>
+            {
+                DeviceFilter::First
+            }
+            None => DeviceFilter::All,
         }
     }
-    fn as_usage(&self) -> Option<f64> {
-        if let LHMDataValue::Usage(val) = self {
-            Some(*val)
-        } else {
-            None
+
+    /// Apply the stale policy to metrics not updated within the configured max age
+    ///
+    /// Metrics that never had a value are left alone, they already read 0.
+    fn check_staleness(&mut self, now: Instant) {
+        let max_age = Duration::from_millis(self.stale.max_age_ms);
+        for metric in Metric::ALL {
+            let Some(last_updated) = self.last_updated.get(&metric) else {
+                continue;
+            };
+            let age = now.saturating_duration_since(*last_updated);
+            if age <= max_age {
+                if self.stale_metrics.remove(&metric) {
+                    info!(metric = metric.name(), "Metric is updating again");
+                }
+                continue;
+            }
+
+            if self.stale_metrics.insert(metric) {
+                warn!(
+                    metric = metric.name(),
+                    age_ms = age.as_millis() as u64,
+                    policy = ?self.stale.policy,
+                    "Metric is stale"
+                );
+            }
+            match self.stale.policy {
+                StalePolicy::Hold => {}
+                StalePolicy::Zero => self.readings.set(metric, 0.0),
+                StalePolicy::Blank => self.readings.set(metric, f64::NAN),
+            }
         }
     }
-    fn as_frequency(&self) -> Option<f64> {
-        if let LHMDataValue::Frequency(val) = self {
-            Some(*val)
-        } else {
-            None
+
+    /// Whether `metric` has been read and isn't stale
+    pub fn has_data(&self, metric: Metric) -> bool {
+        self.last_updated.contains_key(&metric) && !self.stale_metrics.contains(&metric)
+    }
+
+    /// Highest value of each metric, from this session or LHM's own Max column
+    pub fn peak_readings(&self) -> SensorReadings {
+        let mut peak = self.readings;
+        for metric in Metric::ALL {
+            let session_max = self.session_ranges.get(&metric).map(|range| range.max);
+            let lhm_max = self.lhm_max.get(&metric).copied();
+            let max = match (session_max, lhm_max) {
+                (Some(session), Some(lhm)) => Some(session.max(lhm)),
+                (session, lhm) => session.or(lhm),
+            };
+            if let Some(max) = max {
+                peak.set(metric, max);
+            }
         }
+        peak
     }
-    fn as_rpm(&self) -> Option<f64> {
-        if let LHMDataValue::Rpm(val) = self {
-            Some(*val)
-        } else {
-            None
+
+    /// Carry the state of a reader built from an older config over: session
+    /// ranges and dropped sample counts, so peaks and the shutdown summary
+    /// cover the whole session, the last readings and their age, so stale
+    /// detection carries on, and sensors discovered the same way, so
+    /// discovery doesn't run and warn again
+    pub fn inherit_session(&mut self, previous: &SensorReader) {
+        self.session_ranges.clone_from(&previous.session_ranges);
+        self.lhm_max.clone_from(&previous.lhm_max);
+        self.plausibility.inherit(&previous.plausibility);
+
+        for metric in Metric::ALL {
+            self.readings.set(metric, previous.readings.get(metric));
+        }
+        self.readings.all_temperature_unit = previous.readings.all_temperature_unit;
+        self.last_updated.clone_from(&previous.last_updated);
+        self.stale_metrics.clone_from(&previous.stale_metrics);
+        self.schema = previous.schema;
+
+        for metric in Metric::ALL {
+            if let Some(sensor_ids) = previous.discovered.get(&metric)
+                && self.undiscovered.contains(&metric)
+                && self.device_filter(metric) == previous.device_filter(metric)
+            {
+                self.undiscovered
+                    .retain(|undiscovered| *undiscovered != metric);
+                for sensor_id in sensor_ids {
+                    self.sensor_ids.push((sensor_id.clone(), metric));
+                }
+                self.discovered.insert(metric, sensor_ids.clone());
+            }
         }
+        self.discovery_reported
+            .extend(previous.discovery_reported.iter().copied());
     }
-}
 
-impl<'de> Deserialize<'de> for LHMDataValue {
-    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        struct LHMDataValueVisitor;
-        impl<'de> serde::de::Visitor<'de> for LHMDataValueVisitor {
-            type Value = LHMDataValue;
-
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("a string representing a sensor value")
+    /// Log the lowest and highest value of each metric seen since startup, and
+    /// how many samples were dropped as implausible
+    pub fn log_session_ranges(&self) {
+        self.plausibility.log_dropped();
+        for metric in Metric::ALL {
+            if let Some(range) = self.session_ranges.get(&metric) {
+                info!(
+                    metric = metric.name(),
+                    min = range.min,
+                    max = range.max,
+                    "Session range"
+                );
             }
+        }
+    }
 
-            fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E>
-            where
-                E: serde::de::Error,
+    /// Also read these sensors by ID on every update, for [`Self::sensor_values`]
+    pub fn track_sensors<'a>(&mut self, sensor_ids: impl IntoIterator<Item = &'a str>) {
+        for sensor_id in sensor_ids {
+            if !self
+                .tracked_sensors
+                .iter()
+                .any(|tracked| tracked == sensor_id)
             {
-                let Some((val, unit)) = v.split_once(' ') else {
-                    return Ok(LHMDataValue::Unknown);
-                };
-                match unit {
-                    "°C" => val
-                        .parse::<f64>()
-                        .map(|v| LHMDataValue::Temperature(v, TemperatureUnit::Celsius))
-                        .map_err(|_| E::custom(format!("Invalid temperature value: {}", v))),
-                    "°F" => val
-                        .parse::<f64>()
-                        .map(|v| LHMDataValue::Temperature(v, TemperatureUnit::Fahrenheit))
-                        .map_err(|_| E::custom(format!("Invalid temperature value: {}", v))),
-                    "W" => val
-                        .parse::<f64>()
-                        .map(|v| LHMDataValue::Power(v))
-                        .map_err(|_| E::custom(format!("Invalid power value: {}", v))),
-                    "%" => val
-                        .parse::<f64>()
-                        .map(|v| LHMDataValue::Usage(v))
-                        .map_err(|_| E::custom(format!("Invalid usage value: {}", v))),
-                    "MHz" => val
-                        .parse::<f64>()
-                        .map(|v| LHMDataValue::Frequency(v))
-                        .map_err(|_| E::custom(format!("Invalid frequency value: {}", v))),
-                    "RPM" => val
-                        .parse::<f64>()
-                        .map(|v| LHMDataValue::Rpm(v))
-                        .map_err(|_| E::custom(format!("Invalid RPM value: {}", v))),
-                    _ => Ok(LHMDataValue::Unknown),
-                }
+                self.tracked_sensors.push(sensor_id.to_string());
             }
         }
-        deserializer.deserialize_str(LHMDataValueVisitor)
     }
-}
 
-#[derive(Debug, Deserialize)]
-struct LHMDataChildren {
-    #[serde(rename = "Value")]
-    value: LHMDataValue,
-    #[serde(rename = "HardwareId")]
-    hardware_id: Option<String>,
-    #[serde(rename = "SensorId")]
-    sensor_id: Option<String>,
-    #[serde(rename = "Children")]
-    children: Vec<LHMDataChildren>,
+    pub fn sensor_values(&self) -> &HashMap<String, f64> {
+        &self.sensor_values
+    }
+
+    pub fn polling_period(&self) -> u32 {
+        self.readings.polling_period
+    }
+
+    pub fn readings(&self) -> &SensorReadings {
+        &self.readings
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Config;
+
+    fn new_reader(toml: &str) -> SensorReader {
+        let config = Config::from_toml(toml).unwrap();
+        SensorReader::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_fan_found_regardless_of_group_order() {
+        // Fans listed first and nested under a second Super I/O controller
+        let data: LHMData = serde_json::from_str(
+            r#"{"Children": [{"Text": "PC", "Value": "", "Children": [
+                {"Text": "Board", "Value": "", "HardwareId": "/motherboard", "Children": [
+                    {"Text": "ITE IT8689E", "Value": "", "HardwareId": "/lpc/it8689e/0", "Children": []},
+                    {"Text": "ITE IT8792E", "Value": "", "HardwareId": "/lpc/it8792e/0", "Children": [
+                        {"Text": "Fans", "Value": "", "Children": [
+                            {"Text": "CPU Fan", "Value": "1320 RPM", "SensorId": "/lpc/it8792e/0/fan/0", "Children": []}
+                        ]},
+                        {"Text": "Temperatures", "Value": "", "Children": []}
+                    ]}
+                ]}
+            ]}]}"#,
+        )
+        .unwrap();
+
+        let mut explicit = new_reader("[sensors]\ncpu_cooler_rpm = \"/lpc/it8792e/0/fan/0\"");
+        explicit.apply_data(&data).unwrap();
+        assert_eq!(explicit.readings().cpu_cooler_rpm, 1320.0);
+
+        let mut discovered = new_reader("");
+        discovered.apply_data(&data).unwrap();
+        assert_eq!(discovered.readings().cpu_cooler_rpm, 1320.0);
+    }
+
+    #[test]
+    fn test_any_quantity_maps_to_any_slot() {
+        let data: LHMData =
+            serde_json::from_str(include_str!("../testdata/lhm-pre-release.json")).unwrap();
+        let mut reader = new_reader(
+            r#"
+            [sensors]
+            gpu_power = "/gpu-nvidia/0/smalldata/1"
+            cpu_usage = "/lpc/nct6798d/0/control/1"
+            "#,
+        );
+        reader.apply_data(&data).unwrap();
+
+        assert_eq!(reader.readings().gpu_power, 6144.0);
+        assert_eq!(reader.readings().cpu_usage, 42.0);
+    }
+
+    #[test]
+    fn test_peak_readings_combine_session_and_lhm_max() {
+        let data = |cpu_temp: &str, cpu_max: &str| -> LHMData {
+            serde_json::from_str(&format!(
+                r#"{{"Children": [{{"Text": "PC", "Value": "", "Children": [
+                    {{"Text": "Package", "Value": "{cpu_temp}", "Max": "{cpu_max}",
+                      "SensorId": "/amdcpu/0/temperature/2", "Children": []}},
+                    {{"Text": "Package", "Value": "60,0 W", "SensorId": "/amdcpu/0/power/0", "Children": []}}
+                ]}}]}}"#
+            ))
+            .unwrap()
+        };
+        let mut reader = new_reader(
+            r#"
+            [sensors]
+            cpu_temp = "/amdcpu/0/temperature/2"
+            cpu_power = "/amdcpu/0/power/0"
+            "#,
+        );
+
+        // LHM saw a higher value before we started
+        reader.apply_data(&data("71.0 °C", "88.0 °C")).unwrap();
+        assert_eq!(reader.peak_readings().cpu_temp, 88.0);
+
+        // LHM Min/Max reset after the session saw 71 °C
+        reader.apply_data(&data("52.0 °C", "52.0 °C")).unwrap();
+        let peak = reader.peak_readings();
+        assert_eq!(peak.cpu_temp, 71.0);
+        assert_eq!(peak.cpu_power, 60.0);
+        assert_eq!(reader.readings().cpu_temp, 52.0);
+        assert_eq!(
+            reader.session_ranges[&Metric::CpuTemp],
+            MetricRange {
+                min: 52.0,
+                max: 71.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_aggregates_across_devices() {
+        let data: LHMData = serde_json::from_str(
+            r#"{"Children": [{"Text": "PC", "Value": "", "Children": [
+                {"Text": "AMD EPYC 7302", "Value": "", "HardwareId": "/amdcpu/0", "Children": [
+                    {"Text": "Package", "Value": "61.0 °C", "SensorId": "/amdcpu/0/temperature/2", "Children": []},
+                    {"Text": "Package", "Value": "110 W", "SensorId": "/amdcpu/0/power/0", "Children": []}
+                ]},
+                {"Text": "AMD EPYC 7302", "Value": "", "HardwareId": "/amdcpu/1", "Children": [
+                    {"Text": "Package", "Value": "67.5 °C", "SensorId": "/amdcpu/1/temperature/2", "Children": []},
+                    {"Text": "Package", "Value": "90 W", "SensorId": "/amdcpu/1/power/0", "Children": []}
+                ]}
+            ]}]}"#,
+        )
+        .unwrap();
+
+        let mut reader = new_reader(
+            r#"
+            [aggregation]
+            cpu_temp = "max"
+            cpu_power = "sum"
+            "#,
+        );
+        reader.apply_data(&data).unwrap();
+        assert_eq!(reader.readings().cpu_temp, 67.5);
+        assert_eq!(reader.readings().cpu_power, 200.0);
+
+        let mut selected = new_reader("[devices]\ncpu = 1");
+        selected.apply_data(&data).unwrap();
+        assert_eq!(selected.readings().cpu_temp, 67.5);
+        assert_eq!(selected.readings().cpu_power, 90.0);
+
+        let mut first = new_reader("");
+        first.apply_data(&data).unwrap();
+        assert_eq!(first.readings().cpu_power, 110.0);
+    }
+
+    #[test]
+    fn test_stale_metrics_follow_policy() {
+        let data: LHMData = serde_json::from_str(
+            r#"{"Children": [{"Text": "PC", "Value": "", "Children": [
+                {"Text": "Package", "Value": "45.0 °C", "SensorId": "/amdcpu/0/temperature/2", "Children": []}
+            ]}]}"#,
+        )
+        .unwrap();
+        let config = |policy: &str| {
+            format!(
+                "[sensors]\ncpu_temp = \"/amdcpu/0/temperature/2\"\n\
+                 [stale]\nmax_age_ms = 3000\npolicy = \"{policy}\""
+            )
+        };
+        let later = Instant::now() + Duration::from_secs(10);
+
+        let mut hold = new_reader(&config("hold"));
+        hold.apply_data(&data).unwrap();
+        hold.check_staleness(Instant::now());
+        assert!(hold.stale_metrics.is_empty());
+        hold.check_staleness(later);
+        assert_eq!(hold.readings().cpu_temp, 45.0);
+        assert!(hold.stale_metrics.contains(&Metric::CpuTemp));
+
+        let mut zero = new_reader(&config("zero"));
+        zero.apply_data(&data).unwrap();
+        zero.check_staleness(later);
+        assert_eq!(zero.readings().cpu_temp, 0.0);
+
+        let mut blank = new_reader(&config("blank"));
+        blank.apply_data(&data).unwrap();
+        blank.check_staleness(later);
+        assert!(blank.readings().cpu_temp.is_nan());
+
+        // A fresh value clears the stale state
+        blank.apply_data(&data).unwrap();
+        blank.check_staleness(Instant::now());
+        assert_eq!(blank.readings().cpu_temp, 45.0);
+        assert!(blank.stale_metrics.is_empty());
+    }
+
+    #[test]
+    fn test_inherit_session_keeps_discovery_and_age() {
+        let data: LHMData =
+            serde_json::from_str(include_str!("../testdata/lhm-pre-release.json")).unwrap();
+        let mut previous = new_reader("[stale]\nmax_age_ms = 3000");
+        previous.apply_data(&data).unwrap();
+        previous.check_staleness(Instant::now() + Duration::from_secs(10));
+        assert!(previous.stale_metrics.contains(&Metric::CpuTemp));
+
+        let mut reader = new_reader("[stale]\nmax_age_ms = 3000\n[display]\nvalues = \"peak\"");
+        reader.inherit_session(&previous);
+        assert!(reader.undiscovered.is_empty());
+        assert_eq!(reader.sensor_ids, previous.sensor_ids);
+        assert_eq!(reader.readings().cpu_temp, 47.0);
+        assert_eq!(reader.last_updated, previous.last_updated);
+        assert!(reader.stale_metrics.contains(&Metric::CpuTemp));
+        assert_eq!(reader.peak_readings(), previous.peak_readings());
+
+        // Another device selection discovers again
+        let mut reader = new_reader("[devices]\ngpu = 1");
+        reader.inherit_session(&previous);
+        assert!(reader.undiscovered.contains(&Metric::GpuTemp));
+        assert!(!reader.undiscovered.contains(&Metric::CpuTemp));
+    }
+
+    #[test]
+    fn test_tracked_sensors() {
+        let data: LHMData =
+            serde_json::from_str(include_str!("../testdata/lhm-pre-release.json")).unwrap();
+        let mut reader = new_reader("");
+        reader.track_sensors(["/gpu-nvidia/0/smalldata/1", "/missing/0/power/0"]);
+        reader.apply_data(&data).unwrap();
+
+        assert_eq!(reader.sensor_values()["/gpu-nvidia/0/smalldata/1"], 6144.0);
+        assert!(!reader.sensor_values().contains_key("/missing/0/power/0"));
+    }
+
+    #[test]
+    fn test_calibration_applies_to_readings_and_peaks() {
+        let data: LHMData =
+            serde_json::from_str(include_str!("../testdata/lhm-pre-release.json")).unwrap();
+        let mut reader = new_reader(
+            r#"
+            [sensors.calibration]
+            cpu_temp = { offset = -10.0 }
+            cpu_cooler_rpm = { multiplier = 0.5 }
+            "#,
+        );
+        reader.apply_data(&data).unwrap();
+
+        assert_eq!(reader.readings().cpu_temp, 37.0);
+        assert_eq!(reader.readings().cpu_cooler_rpm, 602.0);
+        assert_eq!(reader.peak_readings().cpu_temp, 74.0);
+    }
+
+    #[test]
+    fn test_implausible_values_dropped() {
+        let data = |rpm: &str, max: &str| -> LHMData {
+            serde_json::from_str(&format!(
+                r#"{{"Children": [{{"Text": "PC", "Value": "", "Children": [
+                    {{"Text": "CPU Fan", "Value": "{rpm}", "Max": "{max}",
+                      "SensorId": "/lpc/nct6798d/0/fan/1", "Children": []}}
+                ]}}]}}"#
+            ))
+            .unwrap()
+        };
+        let mut reader = new_reader("[plausibility]\ncpu_cooler_rpm = { max = 5000 }");
+
+        reader.apply_data(&data("1200 RPM", "1300 RPM")).unwrap();
+        reader.apply_data(&data("65535 RPM", "65535 RPM")).unwrap();
+        assert_eq!(reader.readings().cpu_cooler_rpm, 1200.0);
+        assert_eq!(reader.peak_readings().cpu_cooler_rpm, 1300.0);
+    }
+
+    #[test]
+    fn test_invalid_endpoint_reports_url() {
+        let config = Config::from_toml(
+            r#"
+            [lhm]
+            url = "127.0.0.1 port 8085"
+            "#,
+        )
+        .unwrap();
+        let Err(err) = SensorReader::new(&config) else {
+            panic!("Expected invalid URL to be rejected");
+        };
+        assert!(err.to_string().contains("127.0.0.1 port 8085"));
+    }
+
+    #[test]
+    fn test_readings_from_legacy_fixture() {
+        let mut data: LHMData =
+            serde_json::from_str(include_str!("../testdata/ohm-legacy.json")).unwrap();
+        assert_eq!(data.normalize(), Schema::Legacy);
+        let mut reader = new_reader("");
+        reader.apply_data(&data).unwrap();
+
+        let readings = reader.readings();
+        assert_eq!(readings.cpu_temp, 54.3);
+        assert_eq!(readings.cpu_power, 64.2);
+        assert_eq!(readings.cpu_usage, 23.4);
+        assert_eq!(readings.cpu_freq, 3992.0);
+        assert_eq!(readings.cpu_cooler_rpm, 1046.0);
+        assert_eq!(readings.gpu_temp, 68.0);
+        assert_eq!(readings.gpu_power, 141.0);
+        assert_eq!(readings.gpu_usage, 96.0);
+        assert_eq!(readings.gpu_freq, 1340.0);
+    }
+
+    #[test]
+    fn test_readings_from_pre_release_fixture() {
+        let data: LHMData =
+            serde_json::from_str(include_str!("../testdata/lhm-pre-release.json")).unwrap();
+        let mut reader = new_reader("");
+        reader.apply_data(&data).unwrap();
+
+        let readings = reader.readings();
+        assert_eq!(readings.cpu_temp, 47.0);
+        assert_eq!(readings.cpu_power, 38.4);
+        assert_eq!(readings.cpu_usage, 12.6);
+        assert_eq!(readings.cpu_freq, 3912.7);
+        assert_eq!(readings.cpu_cooler_rpm, 1204.0);
+        assert_eq!(readings.gpu_temp, 62.0);
+        assert_eq!(readings.gpu_power, 274.9);
+        assert_eq!(readings.gpu_usage, 87.0);
+        assert_eq!(readings.gpu_freq, 1905.0);
+    }
 
     #[test]
     fn test_read_sensor_values_from_ohm() {
-        let mut reader = SensorReader::new().expect("Failed to initialize SensorReader");
+        let config = Config::default();
+        let mut reader = SensorReader::new(&config).expect("Failed to initialize SensorReader");
         reader.update().expect("Failed to read sensors");
 
         let readings = reader.readings();
@@ -1,4 +1,6 @@
-#[derive(Debug, PartialEq, Clone, Copy)]
+use serde::Deserialize;
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
 pub struct SensorReadings {
     pub cpu_temp: f64,
     pub cpu_power: f64,
@@ -13,16 +15,95 @@ pub struct SensorReadings {
     pub polling_period: u32,
     pub all_temperature_unit: TemperatureUnit,
 }
-#[derive(Debug, PartialEq, Clone, Copy)]
+
+impl SensorReadings {
+    pub fn get(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::CpuTemp => self.cpu_temp,
+            Metric::CpuPower => self.cpu_power,
+            Metric::CpuUsage => self.cpu_usage,
+            Metric::CpuFreq => self.cpu_freq,
+            Metric::CpuCoolerRpm => self.cpu_cooler_rpm,
+            Metric::GpuTemp => self.gpu_temp,
+            Metric::GpuPower => self.gpu_power,
+            Metric::GpuUsage => self.gpu_usage,
+            Metric::GpuFreq => self.gpu_freq,
+        }
+    }
+
+    pub fn set(&mut self, metric: Metric, value: f64) {
+        let field = match metric {
+            Metric::CpuTemp => &mut self.cpu_temp,
+            Metric::CpuPower => &mut self.cpu_power,
+            Metric::CpuUsage => &mut self.cpu_usage,
+            Metric::CpuFreq => &mut self.cpu_freq,
+            Metric::CpuCoolerRpm => &mut self.cpu_cooler_rpm,
+            Metric::GpuTemp => &mut self.gpu_temp,
+            Metric::GpuPower => &mut self.gpu_power,
+            Metric::GpuUsage => &mut self.gpu_usage,
+            Metric::GpuFreq => &mut self.gpu_freq,
+        };
+        *field = value;
+    }
+}
+
+/// A single displayable value, one per sensor field of [`SensorReadings`]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    CpuTemp,
+    CpuPower,
+    CpuUsage,
+    CpuFreq,
+    CpuCoolerRpm,
+    GpuTemp,
+    GpuPower,
+    GpuUsage,
+    GpuFreq,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 9] = [
+        Metric::CpuTemp,
+        Metric::CpuPower,
+        Metric::CpuUsage,
+        Metric::CpuFreq,
+        Metric::CpuCoolerRpm,
+        Metric::GpuTemp,
+        Metric::GpuPower,
+        Metric::GpuUsage,
+        Metric::GpuFreq,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Metric> {
+        Metric::ALL.into_iter().find(|metric| metric.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::CpuTemp => "cpu_temp",
+            Metric::CpuPower => "cpu_power",
+            Metric::CpuUsage => "cpu_usage",
+            Metric::CpuFreq => "cpu_freq",
+            Metric::CpuCoolerRpm => "cpu_cooler_rpm",
+            Metric::GpuTemp => "gpu_temp",
+            Metric::GpuPower => "gpu_power",
+            Metric::GpuUsage => "gpu_usage",
+            Metric::GpuFreq => "gpu_freq",
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
 #[repr(u8)]
 #[allow(dead_code)]
 pub enum TemperatureUnit {
+    #[default]
     Celsius = 0,
     Fahrenheit = 1,
 }
 
 impl TemperatureUnit {
-    #[allow(dead_code)]
     pub fn to_str(&self) -> &'static str {
         match self {
             TemperatureUnit::Celsius => "C",
@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+// Constants
+#[cfg(windows)]
+const SIGNAL_POLL_INTERVAL_MS: u64 = 50;
+const FORCED_EXIT_CODE: i32 = 1;
+
+/// Shutdown request that wakes every thread sleeping on it
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    state: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trigger shutdown on SIGTERM and SIGINT, plus SIGBREAK on Windows
+    ///
+    /// A second signal ends the process at once, for when the graceful
+    /// shutdown is stuck waiting on LHM or the display.
+    pub fn from_signals() -> Result<Self> {
+        let shutdown = Self::new();
+        register_signals(shutdown.clone())?;
+        info!("Shutdown handlers registered");
+        Ok(shutdown)
+    }
+
+    pub fn trigger(&self) {
+        let (requested, wakeup) = &*self.state;
+        *requested.lock().unwrap_or_else(|err| err.into_inner()) = true;
+        wakeup.notify_all();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        let (requested, _) = &*self.state;
+        *requested.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Sleep for `duration` unless shutdown is triggered first, returning
+    /// whether it was
+    pub fn sleep(&self, duration: Duration) -> bool {
+        self.sleep_until(Instant::now() + duration)
+    }
+
+    /// Sleep until `deadline` unless shutdown is triggered first, returning
+    /// whether it was
+    pub fn sleep_until(&self, deadline: Instant) -> bool {
+        let (requested, wakeup) = &*self.state;
+        let mut requested = requested.lock().unwrap_or_else(|err| err.into_inner());
+        while !*requested {
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            requested = wakeup
+                .wait_timeout(requested, remaining)
+                .unwrap_or_else(|err| err.into_inner())
+                .0;
+        }
+        *requested
+    }
+}
+
+#[cfg(unix)]
+fn register_signals(shutdown: Shutdown) -> Result<()> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut signals =
+        Signals::new([SIGTERM, SIGINT]).context("Failed to register shutdown signal handlers")?;
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            if shutdown.is_triggered() {
+                force_exit();
+            }
+            info!(signal, "Received shutdown signal");
+            shutdown.trigger();
+        }
+    });
+    Ok(())
+}
+
+// Signal handlers can only set a flag safely, so a watcher thread forwards it
+#[cfg(windows)]
+fn register_signals(shutdown: Shutdown) -> Result<()> {
+    use signal_hook::consts::{SIGBREAK, SIGINT, SIGTERM};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let flag = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGTERM, flag.clone())
+        .context("Failed to register SIGTERM handler")?;
+    signal_hook::flag::register(SIGINT, flag.clone())
+        .context("Failed to register SIGINT handler")?;
+    signal_hook::flag::register(SIGBREAK, flag.clone())
+        .context("Failed to register SIGBREAK handler")?;
+
+    std::thread::spawn(move || {
+        loop {
+            if flag.swap(false, Ordering::Relaxed) {
+                if shutdown.is_triggered() {
+                    force_exit();
+                }
+                info!("Received shutdown signal");
+                shutdown.trigger();
+            }
+            std::thread::sleep(Duration::from_millis(SIGNAL_POLL_INTERVAL_MS));
+        }
+    });
+    Ok(())
+}
+
+fn force_exit() -> ! {
+    warn!("Received another shutdown signal, exiting without cleanup");
+    std::process::exit(FORCED_EXIT_CODE);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sleep_runs_to_deadline() {
+        let shutdown = Shutdown::new();
+        let start = Instant::now();
+        assert!(!shutdown.sleep(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_trigger_wakes_sleepers() {
+        let shutdown = Shutdown::new();
+        let trigger = shutdown.clone();
+        let start = Instant::now();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            trigger.trigger();
+        });
+
+        assert!(shutdown.sleep(Duration::from_secs(30)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(shutdown.is_triggered());
+        handle.join().unwrap();
+
+        // Later sleeps return immediately
+        assert!(shutdown.sleep(Duration::from_secs(30)));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::ch_170::{CH170Display, DisplayMode};
+use crate::sensor_readings::{Metric, SensorReadings};
+use crate::shutdown::Shutdown;
+use anyhow::{Context, Result};
+use std::time::Duration;
+use tracing::info;
+
+// Constants
+const STEPS_PER_MODE: u32 = 20;
+const STEP_DELAY_MS: u64 = 150;
+
+/// Sweep every display mode from zero to full scale, to check all digits and
+/// indicators light up
+pub fn run(display: &mut CH170Display, shutdown: &Shutdown) -> Result<()> {
+    for mode in DisplayMode::ALL {
+        info!(mode = mode.name(), "Showing test pattern");
+        display.set_mode(mode);
+        for step in 0..=STEPS_PER_MODE {
+            let readings = pattern_readings(step as f64 / STEPS_PER_MODE as f64);
+            display
+                .update(&readings)
+                .context("Failed to update display")?;
+            if shutdown.sleep(Duration::from_millis(STEP_DELAY_MS)) {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Readings at `fraction` of each metric's full scale on the display
+fn pattern_readings(fraction: f64) -> SensorReadings {
+    let mut readings = SensorReadings::default();
+    for metric in Metric::ALL {
+        let full_scale = match metric {
+            Metric::CpuTemp | Metric::GpuTemp => 99.0,
+            Metric::CpuUsage | Metric::GpuUsage => 100.0,
+            Metric::CpuPower | Metric::GpuPower => 999.0,
+            Metric::CpuFreq | Metric::GpuFreq | Metric::CpuCoolerRpm => 9999.0,
+        };
+        readings.set(metric, (full_scale * fraction).round());
+    }
+    readings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_spans_full_scale() {
+        let start = pattern_readings(0.0);
+        let end = pattern_readings(1.0);
+        for metric in Metric::ALL {
+            assert_eq!(start.get(metric), 0.0);
+            assert!(end.get(metric) >= 99.0, "{}", metric.name());
+        }
+        assert_eq!(pattern_readings(0.5).cpu_usage, 50.0);
+    }
+}